@@ -2,7 +2,8 @@ use crossbeam_channel::{unbounded as channel, Receiver, Sender};
 use env_logger::Env;
 use pbgui_messaging::init;
 use pbgui_messaging::{
-    client_proxy::ConnectParams, event::Event, new_event_handler, thread as pbthread, IMsg, OMsg,
+    client_proxy::ConnectParams, new_event_handler, thread as pbthread, ErrorCallbacks, Mailbox,
+    Request, Signal,
 };
 use pbgui_toolbar::toolbar;
 use pbgui_tree::tree;
@@ -19,10 +20,10 @@ use std::rc::Rc;
 
 fn main() {
     env_logger::from_env(Env::default().default_filter_or("info")).init();
-    // sender, receiver for communicating from secondary thread to primary ui thread
-    let (sender, receiver): (Sender<IMsg>, Receiver<IMsg>) = channel();
+    // mailbox holding the replies from the secondary thread to the primary ui thread
+    let mailbox = Mailbox::new();
     // sender and receiver for communicating from ui thread to secondary thread
-    let (to_thread_sender, to_thread_receiver): (Sender<OMsg>, Receiver<OMsg>) = channel();
+    let (to_thread_sender, to_thread_receiver): (Sender<Request>, Receiver<Request>) = channel();
     // sender to handle quitting
     let to_thread_sender_quit = to_thread_sender.clone();
 
@@ -50,6 +51,7 @@ fn main() {
             WithsListConfig::default(),
         )));
         init::package_withs::init(to_thread_sender.clone());
+        init::connection::init(to_thread_sender.clone());
         // wire up message to terminate secondary thread
         let _quit_slot = pbthread::create_quit_slot(to_thread_sender_quit, app.clone());
 
@@ -95,16 +97,22 @@ fn main() {
             treeview.clone(),
             withs_list.clone(),
             toolbar.clone(),
-            receiver,
+            mailbox.clone(),
+            ErrorCallbacks::new().on_vpin_dialog(|error| eprintln!("vpin dialog: {}", error)),
         );
-        let my_conductor = Conductor::<Event>::new(&app_update);
+        let my_conductor = Conductor::<Signal>::new(&app_update);
         pbthread::create(
             ConnectParams::default(),
             main_ptr,
             my_conductor,
-            sender,
+            mailbox,
             to_thread_receiver,
         )
+        // the secondary thread failed to stop. exit regardless
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            1
+        })
     })
 }
 
@@ -0,0 +1,83 @@
+//! The queries the secondary thread makes of packrat, abstracted so that the
+//! dispatch layer may run against something other than a live database.
+//!
+//! `PackratBackend` covers the data queries and `HealthCheck` the connection
+//! family's diagnostics. A `Connector` opens instances of both, along with a
+//! `CancelQuery` handle for each backend, and is what the secondary thread is
+//! handed in place of ConnectParams. ConnectParams is itself a Connector,
+//! producing a PackratDb, while `MemoryBackend` serves canned data.
+use crate::incoming::ServerInfo;
+use std::time::Duration;
+
+pub(crate) mod error;
+
+pub mod memory;
+pub use memory::MemoryBackend;
+
+pub mod packrat;
+pub use packrat::{ControlConnection, PostgresCancel};
+
+/// The error returned by backend queries
+pub type BackendError = Box<dyn std::error::Error>;
+
+/// The packrat queries used by the secondary thread
+pub trait PackratBackend {
+    /// Retrieve the names of all of the roles
+    fn roles(&mut self) -> Result<Vec<String>, BackendError>;
+
+    /// Retrieve the names of all of the sites
+    fn sites(&mut self) -> Result<Vec<String>, BackendError>;
+
+    /// Retrieve the names of all of the platforms
+    fn platforms(&mut self) -> Result<Vec<String>, BackendError>;
+
+    /// Retrieve the names of all of the packages
+    fn packages(&mut self) -> Result<Vec<String>, BackendError>;
+
+    /// Retrieve the names of the shows. These are the levels at depth 1.
+    fn shows(&mut self) -> Result<Vec<String>, BackendError>;
+
+    /// Retrieve the levels of a show, in order. The show itself comes first,
+    /// followed by each sequence (`show.seq`) and its shots (`show.seq.shot`).
+    ///
+    /// # Arguments
+    /// * `show` - The name of the show
+    fn levels(&mut self, show: &str) -> Result<Vec<String>, BackendError>;
+}
+
+/// Diagnostics about the connection to the backend
+pub trait HealthCheck {
+    /// Time the round trip of a trivial query
+    fn ping(&mut self) -> Result<Duration, BackendError>;
+
+    /// Describe the server
+    fn server_info(&mut self) -> Result<ServerInfo, BackendError>;
+
+    /// Retrieve the role the connection operates as
+    fn current_user(&mut self) -> Result<String, BackendError>;
+}
+
+/// Aborts the query running on a backend. The handle is used from a thread other
+/// than the one running the query.
+pub trait CancelQuery {
+    /// Ask the backend to cancel the query it is running, if any. A cancelled
+    /// query fails with an error.
+    fn cancel(&self) -> Result<(), BackendError>;
+}
+
+/// Opens backends on behalf of the secondary thread. Called again whenever the
+/// connection is lost.
+pub trait Connector {
+    type Backend: PackratBackend;
+    type Health: HealthCheck;
+    type Cancel: CancelQuery + Send + Sync + 'static;
+
+    /// Open a new backend, along with the handle which cancels its queries
+    fn connect(&mut self) -> Result<(Self::Backend, Self::Cancel), BackendError>;
+
+    /// Open a connection used only for health checks
+    fn connect_health(&mut self) -> Result<Self::Health, BackendError>;
+
+    /// Describe what is being connected to, for logging
+    fn describe(&self) -> String;
+}
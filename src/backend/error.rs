@@ -0,0 +1,128 @@
+//! Tells what went wrong with a failed backend query. Postgres errors say so
+//! themselves, by their SQLSTATE code. Errors of other backends are judged by
+//! their message, as a last resort, since the server's messages may be localized.
+use postgres::error::SqlState;
+use std::error::Error;
+
+// find the postgres error, wherever it sits in the chain of sources
+fn postgres_error<'e>(err: &'e (dyn Error + 'static)) -> Option<&'e postgres::Error> {
+    let mut current = Some(err);
+    while let Some(err) = current {
+        if let Some(err) = err.downcast_ref::<postgres::Error>() {
+            return Some(err);
+        }
+        current = err.source();
+    }
+    None
+}
+
+/// The SQLSTATE code reported by postgres, eg "42P01", if any
+pub(crate) fn sql_state(err: &(dyn Error + 'static)) -> Option<String> {
+    postgres_error(err)
+        .and_then(postgres::Error::code)
+        .map(|state| state.code().to_string())
+}
+
+/// Whether a query failed because the connection to the database is broken,
+/// rather than because of the query itself. A postgres error says so itself: the
+/// client is closed, it failed on I/O, or the server reported a SQLSTATE of class
+/// 08, connection exception.
+pub(crate) fn is_connection_error(err: &(dyn Error + 'static)) -> bool {
+    match postgres_error(err) {
+        Some(err) => {
+            let io = err
+                .source()
+                .map_or(false, |source| source.is::<std::io::Error>());
+            let class_08 = err
+                .code()
+                .map_or(false, |state| state.code().starts_with("08"));
+            err.is_closed() || io || class_08
+        }
+        None => mentions_connection_error(&err.to_string()),
+    }
+}
+
+/// Guess from its message whether an error is due to a broken connection. Only
+/// for errors which are not postgres errors.
+pub(crate) fn mentions_connection_error(err: &str) -> bool {
+    let err = err.to_lowercase();
+    [
+        "connection closed",
+        "error communicating with the server",
+        "broken pipe",
+        "connection reset",
+        "server closed the connection",
+        "terminating connection",
+    ]
+    .iter()
+    .any(|needle| err.contains(needle))
+}
+
+/// Whether a query failed because it was cancelled, be it at the application's
+/// request, by the watchdog, or by the server for exceeding its
+/// statement_timeout. A postgres error says so with SQLSTATE 57014,
+/// query_canceled, which does not tell them apart. `InFlight` does.
+pub(crate) fn is_cancellation(err: &(dyn Error + 'static)) -> bool {
+    match postgres_error(err) {
+        Some(err) => err.code() == Some(&SqlState::QUERY_CANCELED),
+        None => mentions_cancellation(&err.to_string()),
+    }
+}
+
+/// Guess from its message whether an error is due to a cancelled query. Only for
+/// errors which are not postgres errors.
+pub(crate) fn mentions_cancellation(err: &str) -> bool {
+    err.contains("canceling statement due to")
+}
+
+/// Guess from its message whether an error is due to a query exceeding the
+/// server's statement_timeout. Only for errors which are not postgres errors.
+pub(crate) fn mentions_statement_timeout(err: &str) -> bool {
+    err.contains("canceling statement due to statement timeout")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::BackendError;
+
+    #[test]
+    fn can_recognize_connection_errors_of_other_backends() {
+        let err: BackendError = "connection closed".into();
+        assert!(is_connection_error(err.as_ref()));
+        let err: BackendError =
+            "error communicating with the server: Broken pipe (os error 32)".into();
+        assert!(is_connection_error(err.as_ref()));
+        let err: BackendError = "relation \"levels\" does not exist".into();
+        assert!(!is_connection_error(err.as_ref()));
+    }
+
+    #[test]
+    fn can_recognize_statement_timeouts_of_other_backends() {
+        assert!(mentions_statement_timeout(
+            "db error: ERROR: canceling statement due to statement timeout"
+        ));
+        assert!(!mentions_statement_timeout(
+            "db error: ERROR: canceling statement due to user request"
+        ));
+    }
+
+    #[test]
+    fn can_recognize_cancellations_of_other_backends() {
+        for message in &[
+            "db error: ERROR: canceling statement due to user request",
+            "db error: ERROR: canceling statement due to statement timeout",
+        ] {
+            let err: BackendError = (*message).into();
+            assert!(is_cancellation(err.as_ref()));
+        }
+        let err: BackendError = "relation \"levels\" does not exist".into();
+        assert!(!is_cancellation(err.as_ref()));
+    }
+
+    #[test]
+    fn errors_of_other_backends_have_no_sql_state() {
+        let err: BackendError = "relation \"levels\" does not exist".into();
+        assert_eq!(sql_state(err.as_ref()), None);
+    }
+}
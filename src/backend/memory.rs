@@ -0,0 +1,300 @@
+//! An in-memory PackratBackend serving canned data. Useful for exercising the
+//! secondary thread without a database.
+use super::*;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+#[derive(Debug, Default)]
+struct MemoryData {
+    roles: Vec<String>,
+    sites: Vec<String>,
+    platforms: Vec<String>,
+    packages: Vec<String>,
+    // levels keyed by show, including the show itself
+    levels: BTreeMap<String, Vec<String>>,
+    // the error returned by the next query
+    failure: Option<String>,
+    // the message the next query panics with
+    panic: Option<String>,
+    // the number of connection attempts which should fail
+    connect_failures: u32,
+    // the number of connection attempts which should panic
+    connect_panics: u32,
+    // how long the next query takes, unless cancelled
+    hold: Option<Duration>,
+    // set by cancel, while a query is running
+    cancelled: bool,
+    // the name of each query made, in order
+    queries: Vec<String>,
+}
+
+/// A PackratBackend, HealthCheck, CancelQuery and Connector serving canned data. Clones
+/// share the same data, so a test may keep a clone to adjust the data or
+/// inspect the queries made after handing the original to the secondary thread.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+    data: Arc<Mutex<MemoryData>>,
+}
+
+fn to_strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+impl MemoryBackend {
+    /// New up an empty MemoryBackend
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn data(&self) -> std::sync::MutexGuard<MemoryData> {
+        self.data.lock().expect("MemoryBackend mutex poisoned")
+    }
+
+    /// Set the roles returned by `roles`
+    pub fn set_roles(&self, roles: &[&str]) {
+        self.data().roles = to_strings(roles);
+    }
+
+    /// Set the sites returned by `sites`
+    pub fn set_sites(&self, sites: &[&str]) {
+        self.data().sites = to_strings(sites);
+    }
+
+    /// Set the platforms returned by `platforms`
+    pub fn set_platforms(&self, platforms: &[&str]) {
+        self.data().platforms = to_strings(platforms);
+    }
+
+    /// Set the packages returned by `packages`
+    pub fn set_packages(&self, packages: &[&str]) {
+        self.data().packages = to_strings(packages);
+    }
+
+    /// Set the levels returned by `levels` for a show. The show is added to the
+    /// front of the levels, and to the shows.
+    ///
+    /// # Arguments
+    /// * `show` - The name of the show
+    /// * `levels` - The sequences and shots, eg `["dev01.rd", "dev01.rd.0001"]`
+    pub fn set_levels(&self, show: &str, levels: &[&str]) {
+        let mut all = vec![show.to_string()];
+        all.extend(to_strings(levels));
+        self.data().levels.insert(show.to_string(), all);
+    }
+
+    /// Fail the next query with the supplied error message
+    pub fn fail_next<I: Into<String>>(&self, error: I) {
+        self.data().failure = Some(error.into());
+    }
+
+    /// Panic with the supplied message during the next query
+    pub fn panic_next<I: Into<String>>(&self, message: I) {
+        self.data().panic = Some(message.into());
+    }
+
+    /// Fail the next `count` connection attempts
+    pub fn fail_connects(&self, count: u32) {
+        self.data().connect_failures = count;
+    }
+
+    /// Panic during the next `count` connection attempts
+    pub fn panic_connects(&self, count: u32) {
+        self.data().connect_panics = count;
+    }
+
+    /// Make the next query take `duration` to complete, unless it is cancelled
+    pub fn hold_next(&self, duration: Duration) {
+        self.data().hold = Some(duration);
+    }
+
+    /// The name of each query made so far, in order. Cancellations are recorded
+    /// as "cancel".
+    pub fn queries(&self) -> Vec<String> {
+        self.data().queries.clone()
+    }
+
+    fn query<F>(&self, name: &str, answer: F) -> Result<Vec<String>, BackendError>
+    where
+        F: FnOnce(&MemoryData) -> Result<Vec<String>, BackendError>,
+    {
+        let (hold, panic) = {
+            let mut data = self.data();
+            data.queries.push(name.to_string());
+            // like postgres, a cancel only affects a query which is running
+            data.cancelled = false;
+            (data.hold.take(), data.panic.take())
+        };
+        // panic without holding the lock, so as not to poison it
+        if let Some(message) = panic {
+            panic!("{}", message);
+        }
+        if let Some(hold) = hold {
+            self.wait(hold)?;
+        }
+        let mut data = self.data();
+        if let Some(failure) = data.failure.take() {
+            return Err(failure.into());
+        }
+        answer(&data)
+    }
+
+    // wait out a held query, without holding the lock, so that it may be cancelled
+    fn wait(&self, hold: Duration) -> Result<(), BackendError> {
+        let deadline = Instant::now() + hold;
+        while Instant::now() < deadline {
+            if std::mem::replace(&mut self.data().cancelled, false) {
+                return Err("canceling statement due to user request".into());
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        Ok(())
+    }
+}
+
+impl PackratBackend for MemoryBackend {
+    fn roles(&mut self) -> Result<Vec<String>, BackendError> {
+        self.query("roles", |data| Ok(data.roles.clone()))
+    }
+
+    fn sites(&mut self) -> Result<Vec<String>, BackendError> {
+        self.query("sites", |data| Ok(data.sites.clone()))
+    }
+
+    fn platforms(&mut self) -> Result<Vec<String>, BackendError> {
+        self.query("platforms", |data| Ok(data.platforms.clone()))
+    }
+
+    fn packages(&mut self) -> Result<Vec<String>, BackendError> {
+        self.query("packages", |data| Ok(data.packages.clone()))
+    }
+
+    fn shows(&mut self) -> Result<Vec<String>, BackendError> {
+        self.query("shows", |data| Ok(data.levels.keys().cloned().collect()))
+    }
+
+    fn levels(&mut self, show: &str) -> Result<Vec<String>, BackendError> {
+        self.query(&format!("levels:{}", show), |data| {
+            data.levels
+                .get(show)
+                .cloned()
+                .ok_or_else(|| format!("Unknown show: {}", show).into())
+        })
+    }
+}
+
+impl HealthCheck for MemoryBackend {
+    fn ping(&mut self) -> Result<Duration, BackendError> {
+        self.query("ping", |_| Ok(Vec::new()))?;
+        Ok(Duration::from_millis(0))
+    }
+
+    fn server_info(&mut self) -> Result<ServerInfo, BackendError> {
+        self.query("server_info", |_| Ok(Vec::new()))?;
+        Ok(ServerInfo {
+            host: "memory".to_string(),
+            port: 0,
+            dbname: "memory".to_string(),
+            version: "memory".to_string(),
+            schema: None,
+        })
+    }
+
+    fn current_user(&mut self) -> Result<String, BackendError> {
+        self.query("current_user", |_| Ok(Vec::new()))?;
+        Ok("memory".to_string())
+    }
+}
+
+impl CancelQuery for MemoryBackend {
+    fn cancel(&self) -> Result<(), BackendError> {
+        let mut data = self.data();
+        data.queries.push("cancel".to_string());
+        data.cancelled = true;
+        Ok(())
+    }
+}
+
+impl Connector for MemoryBackend {
+    type Backend = MemoryBackend;
+    type Health = MemoryBackend;
+    type Cancel = MemoryBackend;
+
+    fn connect(&mut self) -> Result<(MemoryBackend, MemoryBackend), BackendError> {
+        let panic = {
+            let mut data = self.data();
+            if data.connect_failures > 0 {
+                data.connect_failures -= 1;
+                return Err("connection refused".into());
+            }
+            let panic = data.connect_panics > 0;
+            data.connect_panics = data.connect_panics.saturating_sub(1);
+            panic
+        };
+        if panic {
+            panic!("panicked while connecting");
+        }
+        Ok((self.clone(), self.clone()))
+    }
+
+    fn connect_health(&mut self) -> Result<MemoryBackend, BackendError> {
+        Ok(self.clone())
+    }
+
+    fn describe(&self) -> String {
+        "in-memory backend".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_serve_canned_data() {
+        let mut backend = MemoryBackend::new();
+        backend.set_roles(&["model", "anim"]);
+        backend.set_levels("dev01", &["dev01.rd", "dev01.rd.0001"]);
+        assert_eq!(backend.roles().unwrap(), vec!["model", "anim"]);
+        assert_eq!(backend.shows().unwrap(), vec!["dev01"]);
+        assert_eq!(
+            backend.levels("dev01").unwrap(),
+            vec!["dev01", "dev01.rd", "dev01.rd.0001"]
+        );
+        assert!(backend.levels("dev02").is_err());
+        assert_eq!(
+            backend.queries(),
+            vec!["roles", "shows", "levels:dev01", "levels:dev02"]
+        );
+    }
+
+    #[test]
+    fn can_fail_next_query() {
+        let mut backend = MemoryBackend::new();
+        backend.fail_next("relation does not exist");
+        assert!(backend.sites().is_err());
+        assert!(backend.sites().is_ok());
+    }
+
+    #[test]
+    fn can_cancel_held_query() {
+        let mut backend = MemoryBackend::new();
+        let canceller = backend.clone();
+        backend.hold_next(Duration::from_secs(10));
+        let handle = std::thread::spawn(move || backend.roles().map_err(|e| e.to_string()));
+        while !canceller.queries().contains(&"roles".to_string()) {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        canceller.cancel().unwrap();
+        let err = handle.join().unwrap().unwrap_err();
+        assert!(err.contains("canceling statement"));
+    }
+
+    #[test]
+    fn can_fail_connects() {
+        let mut backend = MemoryBackend::new();
+        backend.fail_connects(1);
+        assert!(backend.connect().is_err());
+        assert!(backend.connect().is_ok());
+    }
+}
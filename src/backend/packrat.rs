@@ -0,0 +1,132 @@
+//! The PackratBackend implementation backed by a live database
+use super::*;
+use crate::client_proxy::{CancelToken, Client, ClientProxy, ConnectParams};
+use packybara::packrat::PackratDb;
+use packybara::traits::*;
+use std::time::Instant;
+
+impl PackratBackend for PackratDb {
+    fn roles(&mut self) -> Result<Vec<String>, BackendError> {
+        let roles = self.find_all_roles().query()?;
+        // we use std::mem::replace because this should be a bit more efficient
+        // than clone
+        Ok(roles
+            .into_iter()
+            .map(|mut x| std::mem::replace(&mut x.role, String::new()))
+            .collect())
+    }
+
+    fn sites(&mut self) -> Result<Vec<String>, BackendError> {
+        let sites = self.find_all_sites().query()?;
+        Ok(sites
+            .into_iter()
+            .map(|mut x| std::mem::replace(&mut x.name, String::new()))
+            .collect())
+    }
+
+    fn platforms(&mut self) -> Result<Vec<String>, BackendError> {
+        let platforms = self.find_all_platforms().query()?;
+        Ok(platforms
+            .into_iter()
+            .map(|mut x| std::mem::replace(&mut x.name, String::new()))
+            .collect())
+    }
+
+    fn packages(&mut self) -> Result<Vec<String>, BackendError> {
+        let packages = self.find_all_packages().query()?;
+        Ok(packages
+            .into_iter()
+            .map(|mut x| std::mem::replace(&mut x.name, String::new()))
+            .collect())
+    }
+
+    fn shows(&mut self) -> Result<Vec<String>, BackendError> {
+        let shows = self.find_all_levels().depth(1).query()?;
+        Ok(shows
+            .into_iter()
+            .map(|mut x| std::mem::replace(&mut x.show, String::new()))
+            .collect())
+    }
+
+    fn levels(&mut self, show: &str) -> Result<Vec<String>, BackendError> {
+        let levels = self.find_all_levels().show(show).query()?;
+        Ok(levels
+            .into_iter()
+            .map(|mut x| std::mem::replace(&mut x.level, String::new()))
+            .collect())
+    }
+}
+
+/// A connection dedicated to health checks. PackratDb takes ownership of its
+/// Client, so the checks cannot share it.
+pub struct ControlConnection {
+    client: Client,
+    params: ConnectParams,
+}
+
+impl HealthCheck for ControlConnection {
+    fn ping(&mut self) -> Result<Duration, BackendError> {
+        let start = Instant::now();
+        self.client.simple_query("SELECT 1")?;
+        Ok(start.elapsed())
+    }
+
+    fn server_info(&mut self) -> Result<ServerInfo, BackendError> {
+        let row = self.client.query_one(
+            "SELECT current_setting('server_version'), current_database()::text, current_schema()::text",
+            &[],
+        )?;
+        Ok(ServerInfo {
+            host: self.params.host().to_string(),
+            port: self.params.port(),
+            version: row.get(0),
+            dbname: row.get(1),
+            schema: row.get(2),
+        })
+    }
+
+    fn current_user(&mut self) -> Result<String, BackendError> {
+        let row = self.client.query_one("SELECT current_user::text", &[])?;
+        Ok(row.get(0))
+    }
+}
+
+/// Cancels the query running on a PackratDb, using the cancel token of its
+/// Client.
+pub struct PostgresCancel {
+    token: CancelToken,
+    params: ConnectParams,
+}
+
+impl CancelQuery for PostgresCancel {
+    fn cancel(&self) -> Result<(), BackendError> {
+        ClientProxy::cancel(&self.token, &self.params)
+    }
+}
+
+impl Connector for ConnectParams {
+    type Backend = PackratDb;
+    type Health = ControlConnection;
+    type Cancel = PostgresCancel;
+
+    fn connect(&mut self) -> Result<(PackratDb, PostgresCancel), BackendError> {
+        let client = ClientProxy::connect(self.clone())?;
+        let cancel = PostgresCancel {
+            token: client.cancel_token(),
+            params: self.clone(),
+        };
+        Ok((PackratDb::new(client), cancel))
+    }
+
+    fn connect_health(&mut self) -> Result<ControlConnection, BackendError> {
+        let client = ClientProxy::connect(self.clone())?;
+        Ok(ControlConnection {
+            client,
+            params: self.clone(),
+        })
+    }
+
+    fn describe(&self) -> String {
+        self.to_string()
+    }
+}
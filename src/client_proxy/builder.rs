@@ -0,0 +1,694 @@
+//! Assemble an owned ConnectParams from layered sources. From lowest to
+//! highest precedence these are:
+//!
+//! 1. the ConnectParams defaults
+//! 2. a TOML config file
+//! 3. a service from `pg_service.conf`
+//! 4. the `PG*` environment variables
+//! 5. values set explicitly on the builder
+//!
+//! As with libpq, a service only supplies defaults: the environment and explicit
+//! values override it.
+//!
+//! If no password is supplied by any of the above, it is looked up in `.pgpass`.
+//! The Sources returned alongside the ConnectParams record where each value came from.
+use super::{pg_service, pgpass, ConnectParams, SslMode};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The individual connection parameters which may be configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Param {
+    Host,
+    User,
+    Password,
+    Dbname,
+    Port,
+    SslMode,
+    SslRootCert,
+    SslCert,
+    SslKey,
+}
+
+impl Param {
+    /// All of the parameters, in conninfo order
+    pub const ALL: [Param; 9] = [
+        Param::Host,
+        Param::User,
+        Param::Password,
+        Param::Dbname,
+        Param::Port,
+        Param::SslMode,
+        Param::SslRootCert,
+        Param::SslCert,
+        Param::SslKey,
+    ];
+
+    /// The libpq keyword for the parameter, as used in config and service files
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            Param::Host => "host",
+            Param::User => "user",
+            Param::Password => "password",
+            Param::Dbname => "dbname",
+            Param::Port => "port",
+            Param::SslMode => "sslmode",
+            Param::SslRootCert => "sslrootcert",
+            Param::SslCert => "sslcert",
+            Param::SslKey => "sslkey",
+        }
+    }
+
+    /// The environment variable from which the parameter is read
+    pub fn env_var(&self) -> &'static str {
+        match self {
+            Param::Host => "PGHOST",
+            Param::User => "PGUSER",
+            Param::Password => "PGPASSWORD",
+            Param::Dbname => "PGDATABASE",
+            Param::Port => "PGPORT",
+            Param::SslMode => "PGSSLMODE",
+            Param::SslRootCert => "PGSSLROOTCERT",
+            Param::SslCert => "PGSSLCERT",
+            Param::SslKey => "PGSSLKEY",
+        }
+    }
+
+    fn from_keyword(keyword: &str) -> Option<Param> {
+        Param::ALL.iter().find(|p| p.keyword() == keyword).cloned()
+    }
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.keyword())
+    }
+}
+
+/// Where the value of a parameter came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    ConfigFile(PathBuf),
+    Service { name: String, path: PathBuf },
+    Env(&'static str),
+    PgPass(PathBuf),
+    Explicit,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::ConfigFile(path) => write!(f, "config file {}", path.display()),
+            Source::Service { name, path } => {
+                write!(f, "service '{}' in {}", name, path.display())
+            }
+            Source::Env(var) => write!(f, "environment variable {}", var),
+            Source::PgPass(path) => write!(f, "password file {}", path.display()),
+            Source::Explicit => write!(f, "explicitly set"),
+        }
+    }
+}
+
+/// Records the Source of each parameter of a built ConnectParams
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Sources(HashMap<Param, Source>);
+
+impl Sources {
+    /// Retrieve the source of the supplied parameter. Parameters which were
+    /// never set report Source::Default.
+    pub fn get(&self, param: Param) -> &Source {
+        self.0.get(&param).unwrap_or(&Source::Default)
+    }
+}
+
+impl fmt::Display for Sources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines = Param::ALL
+            .iter()
+            .map(|param| format!("{}: {}", param, self.get(*param)))
+            .collect::<Vec<_>>();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Errors encountered while building ConnectParams
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Toml {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    UnknownService {
+        name: String,
+        path: PathBuf,
+    },
+    InvalidValue {
+        param: Param,
+        value: String,
+        source: Source,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => {
+                write!(f, "Unable to read {}: {}", path.display(), error)
+            }
+            ConfigError::Toml { path, error } => {
+                write!(f, "Unable to parse {}: {}", path.display(), error)
+            }
+            ConfigError::UnknownService { name, path } => {
+                write!(f, "Service '{}' not found in {}", name, path.display())
+            }
+            ConfigError::InvalidValue {
+                param,
+                value,
+                source,
+            } => write!(f, "Invalid {} '{}' from {}", param, value, source),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { error, .. } => Some(error),
+            ConfigError::Toml { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+type EnvLookup = Box<dyn Fn(&str) -> Option<String>>;
+
+/// Builds an owned ConnectParams by layering defaults, a config file, a
+/// pg_service.conf service, the environment and explicit values.
+///
+/// # Example
+/// ```ignore
+/// let (params, sources) = ConnectParamsBuilder::new()
+///     .config_file("/etc/packrat/db.toml")
+///     .build()?;
+/// log::info!("connecting with:\n{}", sources);
+/// ```
+pub struct ConnectParamsBuilder {
+    config_file: Option<PathBuf>,
+    service: Option<String>,
+    service_file: Option<PathBuf>,
+    pgpass_file: Option<PathBuf>,
+    use_env: bool,
+    use_pgpass: bool,
+    explicit: Vec<(Param, String)>,
+    env: EnvLookup,
+}
+
+impl Default for ConnectParamsBuilder {
+    fn default() -> Self {
+        Self {
+            config_file: None,
+            service: None,
+            service_file: None,
+            pgpass_file: None,
+            use_env: true,
+            use_pgpass: true,
+            explicit: Vec::new(),
+            env: Box::new(|var| std::env::var(var).ok()),
+        }
+    }
+}
+
+impl ConnectParamsBuilder {
+    /// New up a builder which reads the environment and `~/.pgpass`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read parameters from a TOML config file. The file holds top level
+    /// conninfo keywords (host, user, password, dbname, port, sslmode,
+    /// sslrootcert, sslcert, sslkey) and optionally a service name.
+    pub fn config_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.config_file = Some(path.into());
+        self
+    }
+
+    /// Use the named service from the service file, rather than one named by
+    /// PGSERVICE or the config file. The service's values are still overridden
+    /// by the environment.
+    pub fn service<I: Into<String>>(mut self, name: I) -> Self {
+        self.service = Some(name.into());
+        self
+    }
+
+    /// Override the location of the service file
+    pub fn service_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.service_file = Some(path.into());
+        self
+    }
+
+    /// Override the location of the password file
+    pub fn pgpass_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.pgpass_file = Some(path.into());
+        self
+    }
+
+    /// Enable or disable reading the `PG*` environment variables
+    pub fn use_env(mut self, use_env: bool) -> Self {
+        self.use_env = use_env;
+        self
+    }
+
+    /// Enable or disable the password file lookup
+    pub fn use_pgpass(mut self, use_pgpass: bool) -> Self {
+        self.use_pgpass = use_pgpass;
+        self
+    }
+
+    /// Replace the function used to look up environment variables.
+    pub fn env_lookup<F>(mut self, lookup: F) -> Self
+    where
+        F: Fn(&str) -> Option<String> + 'static,
+    {
+        self.env = Box::new(lookup);
+        self
+    }
+
+    /// Explicitly set a parameter, overriding every other source
+    pub fn set<I: Into<String>>(mut self, param: Param, value: I) -> Self {
+        self.explicit.push((param, value.into()));
+        self
+    }
+
+    /// Explicitly set the host
+    pub fn host<I: Into<String>>(self, host: I) -> Self {
+        self.set(Param::Host, host)
+    }
+
+    /// Explicitly set the user
+    pub fn user<I: Into<String>>(self, user: I) -> Self {
+        self.set(Param::User, user)
+    }
+
+    /// Explicitly set the password
+    pub fn password<I: Into<String>>(self, password: I) -> Self {
+        self.set(Param::Password, password)
+    }
+
+    /// Explicitly set the database name
+    pub fn dbname<I: Into<String>>(self, dbname: I) -> Self {
+        self.set(Param::Dbname, dbname)
+    }
+
+    /// Explicitly set the port
+    pub fn port(self, port: u64) -> Self {
+        self.set(Param::Port, port.to_string())
+    }
+
+    /// Explicitly set the sslmode
+    pub fn sslmode(self, sslmode: SslMode) -> Self {
+        self.set(Param::SslMode, sslmode.to_string())
+    }
+
+    /// Resolve the layers into a ConnectParams.
+    ///
+    /// # Returns
+    /// * Ok((ConnectParams, Sources)) if successful
+    /// * Err(ConfigError) if a file cannot be read, a service cannot be found,
+    ///   or a value is invalid
+    pub fn build(self) -> Result<(ConnectParams, Sources), ConfigError> {
+        let mut layers = Layers::from_defaults();
+        let mut service = None;
+
+        if let Some(path) = self.config_file.as_ref() {
+            let config = read_config_file(path)?;
+            for (key, value) in config {
+                match Param::from_keyword(&key) {
+                    Some(param) => layers.set(param, value, Source::ConfigFile(path.clone())),
+                    None if key == "service" => service = Some(value),
+                    None => log::warn!("Ignoring unknown key '{}' in {}", key, path.display()),
+                }
+            }
+        }
+
+        if self.use_env {
+            if let Some(name) = (self.env)("PGSERVICE") {
+                service = Some(name);
+            }
+        }
+        if let Some(name) = self.service.clone() {
+            service = Some(name);
+        }
+        if let Some(name) = service {
+            self.apply_service(&name, &mut layers)?;
+        }
+
+        // the environment is applied after the service, so that PGHOST and the
+        // like override it, as they do for psql
+        if self.use_env {
+            for param in Param::ALL.iter() {
+                if let Some(value) = (self.env)(param.env_var()) {
+                    layers.set(*param, value, Source::Env(param.env_var()));
+                }
+            }
+        }
+
+        for (param, value) in self.explicit.iter() {
+            layers.set(*param, value.clone(), Source::Explicit);
+        }
+
+        let mut params = layers.to_connect_params()?;
+        if self.use_pgpass && layers.sources.get(Param::Password) == &Source::Default {
+            if let Some(path) = self.pgpass_path() {
+                if let Some(password) = pgpass::find_password(
+                    &path,
+                    &params.host,
+                    params.port,
+                    &params.dbname,
+                    &params.user,
+                ) {
+                    params.password = password;
+                    layers
+                        .sources
+                        .0
+                        .insert(Param::Password, Source::PgPass(path));
+                }
+            }
+        }
+        log::debug!("ConnectParams sources:\n{}", layers.sources);
+        Ok((params, layers.sources))
+    }
+
+    fn apply_service(&self, name: &str, layers: &mut Layers) -> Result<(), ConfigError> {
+        let path = self
+            .service_file
+            .clone()
+            .or_else(|| self.env_path("PGSERVICEFILE"))
+            .or_else(pg_service::default_path)
+            .unwrap_or_else(|| PathBuf::from(".pg_service.conf"));
+        let contents = fs::read_to_string(&path).map_err(|error| ConfigError::Io {
+            path: path.clone(),
+            error,
+        })?;
+        let mut services = pg_service::parse(&contents);
+        let service = services
+            .remove(name)
+            .ok_or_else(|| ConfigError::UnknownService {
+                name: name.to_string(),
+                path: path.clone(),
+            })?;
+        for (key, value) in service {
+            match Param::from_keyword(&key) {
+                Some(param) => layers.set(
+                    param,
+                    value,
+                    Source::Service {
+                        name: name.to_string(),
+                        path: path.clone(),
+                    },
+                ),
+                None => log::warn!("Ignoring unknown keyword '{}' in service '{}'", key, name),
+            }
+        }
+        Ok(())
+    }
+
+    fn pgpass_path(&self) -> Option<PathBuf> {
+        self.pgpass_file
+            .clone()
+            .or_else(|| self.env_path("PGPASSFILE"))
+            .or_else(pgpass::default_path)
+    }
+
+    fn env_path(&self, var: &str) -> Option<PathBuf> {
+        if self.use_env {
+            (self.env)(var).map(PathBuf::from)
+        } else {
+            None
+        }
+    }
+}
+
+// The resolved value and source of each parameter
+struct Layers {
+    values: HashMap<Param, String>,
+    sources: Sources,
+}
+
+impl Layers {
+    fn from_defaults() -> Self {
+        let defaults = ConnectParams::default();
+        let mut values = HashMap::new();
+        values.insert(Param::Host, defaults.host);
+        values.insert(Param::User, defaults.user);
+        values.insert(Param::Password, defaults.password);
+        values.insert(Param::Dbname, defaults.dbname);
+        values.insert(Param::Port, defaults.port.to_string());
+        values.insert(Param::SslMode, defaults.sslmode.to_string());
+        Self {
+            values,
+            sources: Sources::default(),
+        }
+    }
+
+    fn set(&mut self, param: Param, value: String, source: Source) {
+        self.values.insert(param, value);
+        self.sources.0.insert(param, source);
+    }
+
+    fn invalid(&self, param: Param) -> ConfigError {
+        ConfigError::InvalidValue {
+            param,
+            value: self.values.get(&param).cloned().unwrap_or_default(),
+            source: self.sources.get(param).clone(),
+        }
+    }
+
+    fn to_connect_params(&self) -> Result<ConnectParams, ConfigError> {
+        let get = |param| self.values.get(&param).cloned().unwrap_or_default();
+        let port = get(Param::Port)
+            .parse::<u64>()
+            .map_err(|_| self.invalid(Param::Port))?;
+        let sslmode = get(Param::SslMode)
+            .parse::<SslMode>()
+            .map_err(|_| self.invalid(Param::SslMode))?;
+        let mut params = ConnectParams::new(
+            get(Param::Host),
+            get(Param::User),
+            get(Param::Password),
+            get(Param::Dbname),
+            port,
+        )
+        .sslmode(sslmode);
+        params.ssl_root_cert = self.values.get(&Param::SslRootCert).cloned();
+        params.ssl_cert = self.values.get(&Param::SslCert).cloned();
+        params.ssl_key = self.values.get(&Param::SslKey).cloned();
+        Ok(params)
+    }
+}
+
+// Read the top level keys of a TOML config file as strings
+fn read_config_file(path: &Path) -> Result<Vec<(String, String)>, ConfigError> {
+    let contents = fs::read_to_string(path).map_err(|error| ConfigError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let table = contents
+        .parse::<toml::Value>()
+        .map_err(|error| ConfigError::Toml {
+            path: path.to_path_buf(),
+            error,
+        })?;
+    let table = match table {
+        toml::Value::Table(table) => table,
+        _ => return Ok(Vec::new()),
+    };
+    Ok(table
+        .into_iter()
+        .filter_map(|(key, value)| match value {
+            toml::Value::String(value) => Some((key, value)),
+            toml::Value::Integer(value) => Some((key, value.to_string())),
+            _ => {
+                log::warn!("Ignoring non scalar key '{}' in {}", key, path.display());
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{tempdir, TempDir};
+
+    // write contents to the file `name` in `dir`, readable only by its owner, as
+    // a pgpass file must be
+    fn temp_file(dir: &TempDir, name: &str, contents: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        path
+    }
+
+    fn env_from(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<HashMap<_, _>>();
+        move |var| vars.get(var).cloned()
+    }
+
+    #[test]
+    fn defaults_when_nothing_configured() {
+        let (params, sources) = ConnectParamsBuilder::new()
+            .use_env(false)
+            .use_pgpass(false)
+            .build()
+            .unwrap();
+        assert_eq!(params, ConnectParams::default());
+        assert_eq!(sources.get(Param::Host), &Source::Default);
+    }
+
+    #[test]
+    fn layers_take_precedence_in_order() {
+        let dir = tempdir().unwrap();
+        let config = temp_file(
+            &dir,
+            "layers.toml",
+            "host = \"config\"\nuser = \"config\"\nport = 6000\n",
+        );
+        let (params, sources) = ConnectParamsBuilder::new()
+            .config_file(&config)
+            .env_lookup(env_from(&[("PGUSER", "env"), ("PGDATABASE", "envdb")]))
+            .use_pgpass(false)
+            .dbname("explicit")
+            .build()
+            .unwrap();
+        assert_eq!(params.host, "config");
+        assert_eq!(params.user, "env");
+        assert_eq!(params.dbname, "explicit");
+        assert_eq!(params.port, 6000);
+        assert_eq!(sources.get(Param::Host), &Source::ConfigFile(config));
+        assert_eq!(sources.get(Param::User), &Source::Env("PGUSER"));
+        assert_eq!(sources.get(Param::Dbname), &Source::Explicit);
+    }
+
+    #[test]
+    fn can_read_service_named_in_env() {
+        let dir = tempdir().unwrap();
+        let services = temp_file(
+            &dir,
+            "services.conf",
+            "[packrat]\nhost=service-host\nsslmode=require\n",
+        );
+        let services_str = services.to_str().unwrap().to_string();
+        let (params, sources) = ConnectParamsBuilder::new()
+            .env_lookup(move |var| match var {
+                "PGSERVICE" => Some("packrat".to_string()),
+                "PGSERVICEFILE" => Some(services_str.clone()),
+                _ => None,
+            })
+            .use_pgpass(false)
+            .build()
+            .unwrap();
+        assert_eq!(params.host, "service-host");
+        assert_eq!(params.sslmode, SslMode::Require);
+        assert_eq!(
+            sources.get(Param::Host),
+            &Source::Service {
+                name: "packrat".to_string(),
+                path: services
+            }
+        );
+    }
+
+    #[test]
+    fn environment_overrides_service() {
+        let dir = tempdir().unwrap();
+        let services = temp_file(
+            &dir,
+            "services-env.conf",
+            "[packrat]\nhost=service-host\nuser=service-user\n",
+        );
+        let services_str = services.to_str().unwrap().to_string();
+        let (params, sources) = ConnectParamsBuilder::new()
+            .env_lookup(move |var| match var {
+                "PGSERVICE" => Some("packrat".to_string()),
+                "PGSERVICEFILE" => Some(services_str.clone()),
+                "PGHOST" => Some("env-host".to_string()),
+                _ => None,
+            })
+            .use_pgpass(false)
+            .build()
+            .unwrap();
+        assert_eq!(params.host, "env-host");
+        assert_eq!(sources.get(Param::Host), &Source::Env("PGHOST"));
+        // the service still supplies what the environment does not
+        assert_eq!(params.user, "service-user");
+    }
+
+    #[test]
+    fn unknown_service_is_an_error() {
+        let dir = tempdir().unwrap();
+        let services = temp_file(&dir, "noservice.conf", "[other]\nhost=foo\n");
+        let result = ConnectParamsBuilder::new()
+            .use_env(false)
+            .service("packrat")
+            .service_file(services)
+            .build();
+        assert!(matches!(result, Err(ConfigError::UnknownService { .. })));
+    }
+
+    #[test]
+    fn password_is_read_from_pgpass() {
+        let dir = tempdir().unwrap();
+        let pgpass = temp_file(&dir, "pgpass", "127.0.0.1:5432:packrat:fred:secret\n");
+        let (params, sources) = ConnectParamsBuilder::new()
+            .use_env(false)
+            .pgpass_file(&pgpass)
+            .user("fred")
+            .build()
+            .unwrap();
+        assert_eq!(params.password, "secret");
+        assert_eq!(sources.get(Param::Password), &Source::PgPass(pgpass));
+    }
+
+    #[test]
+    fn explicit_password_wins_over_pgpass() {
+        let dir = tempdir().unwrap();
+        let pgpass = temp_file(&dir, "pgpass-explicit", "*:*:*:*:secret\n");
+        let (params, _) = ConnectParamsBuilder::new()
+            .use_env(false)
+            .pgpass_file(pgpass)
+            .password("mine")
+            .build()
+            .unwrap();
+        assert_eq!(params.password, "mine");
+    }
+
+    #[test]
+    fn invalid_port_reports_source() {
+        let result = ConnectParamsBuilder::new()
+            .env_lookup(env_from(&[("PGPORT", "fivefourthreetwo")]))
+            .use_pgpass(false)
+            .build();
+        match result {
+            Err(ConfigError::InvalidValue { param, source, .. }) => {
+                assert_eq!(param, Param::Port);
+                assert_eq!(source, Source::Env("PGPORT"));
+            }
+            _ => panic!("expected an invalid port"),
+        }
+    }
+}
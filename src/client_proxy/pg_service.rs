@@ -0,0 +1,68 @@
+//! Read connection services from a libpq style service file, normally
+//! `~/.pg_service.conf`.
+//!
+//! The file is ini formatted. Each `[section]` names a service, and the
+//! `key=value` lines which follow it are conninfo keywords.
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// The keyword/value pairs making up a single service
+pub type Service = HashMap<String, String>;
+
+/// The default location of the service file for the current user
+pub fn default_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".pg_service.conf"))
+}
+
+/// Parse the contents of a service file into a map of services keyed by name.
+/// Lines which are neither sections nor `key=value` pairs are ignored.
+pub fn parse(contents: &str) -> HashMap<String, Service> {
+    let mut services = HashMap::new();
+    let mut current: Option<String> = None;
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            let name = line[1..line.len() - 1].trim().to_string();
+            services.entry(name.clone()).or_insert_with(Service::new);
+            current = Some(name);
+            continue;
+        }
+        let name = match current.as_ref() {
+            Some(name) => name,
+            None => continue,
+        };
+        let mut pieces = line.splitn(2, '=');
+        if let (Some(key), Some(value)) = (pieces.next(), pieces.next()) {
+            services
+                .get_mut(name)
+                .expect("section was inserted when encountered")
+                .insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    services
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_services() {
+        let services = parse(
+            "# studio services
+[packrat]
+host=db.studio
+port = 5433
+
+[scratch]
+dbname=scratch
+",
+        );
+        assert_eq!(services.len(), 2);
+        assert_eq!(services["packrat"]["host"], "db.studio");
+        assert_eq!(services["packrat"]["port"], "5433");
+        assert_eq!(services["scratch"]["dbname"], "scratch");
+    }
+}
@@ -0,0 +1,146 @@
+//! Look up passwords in a libpq style password file, normally `~/.pgpass`.
+//!
+//! Each non comment line has the form `hostname:port:database:username:password`.
+//! Any of the first four fields may be `*`, which matches anything, and `:` or `\`
+//! may be escaped with a backslash.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The default location of the password file for the current user
+pub fn default_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".pgpass"))
+}
+
+/// Read the password file at `path` and return the password of the first entry
+/// matching the supplied connection details.
+///
+/// # Arguments
+/// * `path` - The path to the password file
+/// * `host` - The host being connected to
+/// * `port` - The port being connected to
+/// * `dbname` - The database name
+/// * `user` - The user name
+///
+/// # Returns
+/// * Some(password) if the file exists, may be used, and has a matching entry
+/// * None otherwise
+pub fn find_password(
+    path: &Path,
+    host: &str,
+    port: u64,
+    dbname: &str,
+    user: &str,
+) -> Option<String> {
+    if !is_private(path) {
+        log::warn!(
+            "password file {} has group or world access; ignoring it",
+            path.display()
+        );
+        return None;
+    }
+    let contents = fs::read_to_string(path).ok()?;
+    lookup(&contents, host, port, dbname, user)
+}
+
+/// Return the password of the first entry in `contents` matching the
+/// supplied connection details.
+pub fn lookup(contents: &str, host: &str, port: u64, dbname: &str, user: &str) -> Option<String> {
+    let port = port.to_string();
+    let wanted = [host, port.as_str(), dbname, user];
+    contents
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(split_fields)
+        .filter(|fields| fields.len() == 5)
+        .find(|fields| {
+            fields[..4]
+                .iter()
+                .zip(wanted.iter())
+                .all(|(field, wanted)| field == "*" || field == wanted)
+        })
+        .map(|mut fields| fields.remove(4))
+}
+
+// split a line on unescaped colons, removing the escapes as we go
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    field.push(escaped);
+                }
+            }
+            ':' if fields.len() < 4 => fields.push(std::mem::replace(&mut field, String::new())),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+#[cfg(unix)]
+fn is_private(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    match fs::metadata(path) {
+        Ok(meta) => meta.permissions().mode() & 0o077 == 0,
+        Err(_) => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_private(path: &Path) -> bool {
+    path.exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGPASS: &str = "# comment line
+db1:5432:packrat:fred:first
+*:*:packrat:fred:second
+*:*:*:*:pass\\:with\\\\escapes
+";
+
+    #[test]
+    fn can_match_exact_entry() {
+        assert_eq!(
+            lookup(PGPASS, "db1", 5432, "packrat", "fred"),
+            Some("first".to_string())
+        );
+    }
+
+    #[test]
+    fn can_match_wildcards() {
+        assert_eq!(
+            lookup(PGPASS, "db2", 5433, "packrat", "fred"),
+            Some("second".to_string())
+        );
+    }
+
+    #[test]
+    fn can_unescape_fields() {
+        assert_eq!(
+            lookup(PGPASS, "db2", 5432, "other", "barney"),
+            Some("pass:with\\escapes".to_string())
+        );
+    }
+
+    #[test]
+    fn returns_none_without_match() {
+        assert_eq!(
+            lookup(
+                "db1:5432:packrat:fred:first",
+                "db1",
+                5432,
+                "packrat",
+                "barney"
+            ),
+            None
+        );
+    }
+}
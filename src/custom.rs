@@ -0,0 +1,126 @@
+//! custom carries the messages of families declared outside of this crate, eg by
+//! an application's own widgets. Their requests are sent as OMsg::Custom, and
+//! answered with IMsg::Custom, signaled as Event::Custom. Each is handled by the
+//! handlers registered for its family. See `thread::registry::Handlers`, and
+//! `event_handler::EventHandlers`.
+//!
+//! A custom request which writes to the database declares the kinds of query it
+//! makes stale, with `CustomMsg::invalidating`, so that the secondary thread
+//! discards their cached results once it has handled the request.
+use crate::{event::Event, IMsg, OMsg, QueryKind, ToIMsg, ToOMsg};
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+/// A message belonging to a family registered by the application. The payload
+/// may be of any type, and is downcast by the family's handlers.
+#[derive(Clone)]
+pub struct CustomMsg {
+    family: &'static str,
+    name: &'static str,
+    payload: Arc<dyn Any + Send + Sync>,
+    invalidates: Vec<QueryKind>,
+}
+
+impl CustomMsg {
+    /// New up a CustomMsg
+    ///
+    /// # Arguments
+    /// * `family` - The name of the family the message belongs to, eg "Shots"
+    /// * `name` - The name of the message within its family, eg "GetShots"
+    /// * `payload` - The details of the message, eg the show to get shots of
+    pub fn new<T: Any + Send + Sync>(family: &'static str, name: &'static str, payload: T) -> Self {
+        Self {
+            family,
+            name,
+            payload: Arc::new(payload),
+            invalidates: Vec::new(),
+        }
+    }
+
+    /// Declare that the message writes to the database, making the cached
+    /// results of `kinds` of query stale once it has been handled
+    pub fn invalidating<I: IntoIterator<Item = QueryKind>>(mut self, kinds: I) -> Self {
+        self.invalidates.extend(kinds);
+        self
+    }
+
+    /// The kinds of query the message makes stale. See `invalidating`.
+    pub fn invalidates(&self) -> &[QueryKind] {
+        &self.invalidates
+    }
+
+    /// The name of the family the message belongs to, eg "Shots"
+    pub fn family(&self) -> &'static str {
+        self.family
+    }
+
+    /// The name of the message within its family, eg "GetShots"
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The payload, if it is a `T`
+    pub fn payload<T: Any>(&self) -> Option<&T> {
+        self.payload.downcast_ref::<T>()
+    }
+
+    /// The Event announcing the message, eg "Shots::GetShots"
+    pub fn event(&self) -> Event {
+        Event::Custom(format!("{}::{}", self.family, self.name))
+    }
+}
+
+impl fmt::Debug for CustomMsg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CustomMsg({}::{})", self.family, self.name)
+    }
+}
+
+/// Messages are equal if they are named alike, share the same payload and
+/// invalidate the same kinds of query
+impl PartialEq for CustomMsg {
+    fn eq(&self, other: &Self) -> bool {
+        self.family == other.family
+            && self.name == other.name
+            && Arc::ptr_eq(&self.payload, &other.payload)
+            && self.invalidates == other.invalidates
+    }
+}
+
+/// Send the message as a request, ie OMsg::Custom
+impl ToOMsg for CustomMsg {
+    fn to_omsg(self) -> OMsg {
+        OMsg::Custom(self)
+    }
+}
+
+/// Post the message as a reply, ie IMsg::Custom
+impl ToIMsg for CustomMsg {
+    fn to_imsg(self) -> IMsg {
+        IMsg::Custom(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_can_be_downcast() {
+        let msg = CustomMsg::new("Shots", "GetShots", "dev01".to_string());
+        assert_eq!(msg.payload::<String>(), Some(&"dev01".to_string()));
+        assert_eq!(msg.payload::<u32>(), None);
+        assert_eq!(msg.event(), Event::Custom("Shots::GetShots".to_string()));
+        assert_eq!(msg.clone(), msg);
+    }
+
+    #[test]
+    fn writes_declare_the_kinds_they_invalidate() {
+        let msg = CustomMsg::new("Roles", "AddRole", "lighting".to_string());
+        assert!(msg.invalidates().is_empty());
+        let msg = msg.invalidating(vec![QueryKind::Roles]);
+        assert_eq!(msg.invalidates(), &[QueryKind::Roles]);
+        assert_eq!(msg.to_omsg().invalidates(), vec![QueryKind::Roles]);
+    }
+}
@@ -3,21 +3,21 @@
 //! The messaging to the application from the db is split between the Event and the IMsg.
 //! The Event signals that a given state has changed.
 //! THe IMsg provides the details of the state change.
+use crate::{mailbox::Seq, outgoing::RequestId};
+#[cfg(feature = "qt")]
 use qt_core::QString;
+#[cfg(feature = "qt")]
 use qt_thread_conductor::traits::*;
+#[cfg(feature = "qt")]
 use qt_widgets::cpp_core::{CppBox, Ref};
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
 
-pub mod vpin_dialog;
-pub use vpin_dialog::VpinDialog;
-
-pub mod packages_tree;
-pub use packages_tree::PackagesTree;
-
-pub mod package_withs;
-pub use package_withs::PackageWiths;
-
-pub mod main_toolbar;
-pub use main_toolbar::MainToolbar;
+pub use crate::family::{
+    connection::Connection, main_toolbar::MainToolbar, package_withs::PackageWiths,
+    packages_tree::PackagesTree, vpin_dialog::VpinDialog,
+};
 /// ergonomics related trait. Convert a nested enum to an event
 pub trait ToEvent {
     fn to_event(self) -> Event;
@@ -29,49 +29,277 @@ pub enum Event {
     PackagesTree(PackagesTree),
     PackageWiths(PackageWiths),
     MainToolbar(MainToolbar),
+    Connection(Connection),
     Error,
+    Stats,
+    /// Announces an IMsg::Custom. Carries the family and name of the message,
+    /// eg "Shots::UpdateShots".
+    Custom(String),
 }
 
+impl Event {
+    /// The string carried by the qt signal, eg "VpinDialog::UpdateRoles"
+    pub fn as_str(&self) -> &str {
+        match self {
+            Event::VpinDialog(vpin_dialog) => vpin_dialog.as_str(),
+            Event::PackagesTree(packages_tree) => packages_tree.as_str(),
+            Event::PackageWiths(package_withs) => package_withs.as_str(),
+            Event::MainToolbar(main_toolbar) => main_toolbar.as_str(),
+            Event::Connection(connection) => connection.as_str(),
+            Event::Error => "Error",
+            Event::Stats => "Stats",
+            Event::Custom(name) => name.as_str(),
+        }
+    }
+
+    /// The name of the family the Event belongs to, eg "VpinDialog"
+    pub fn family(&self) -> &str {
+        match self {
+            Event::VpinDialog(_) => "VpinDialog",
+            Event::PackagesTree(_) => "PackagesTree",
+            Event::PackageWiths(_) => "PackageWiths",
+            Event::MainToolbar(_) => "MainToolbar",
+            Event::Connection(_) => "Connection",
+            Event::Error => "Error",
+            Event::Stats => "Stats",
+            Event::Custom(name) => name.split("::").next().unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(feature = "qt")]
 impl ToQString for Event {
     fn to_qstring(&self) -> CppBox<QString> {
-        match &self {
-            &Event::VpinDialog(vpin_dialog) => vpin_dialog.to_qstring(),
-            &Event::PackagesTree(packages_tree) => packages_tree.to_qstring(),
-            &Event::PackageWiths(package_withs) => package_withs.to_qstring(),
-            &Event::MainToolbar(main_toolbar) => main_toolbar.to_qstring(),
-            &Event::Error => QString::from_std_str("Error"),
-        }
+        QString::from_std_str(self.as_str())
     }
 }
 
-impl FromQString for Event {
-    fn from_qstring(qs: Ref<QString>) -> Self {
-        let test_str = qs.to_std_string();
-        match test_str.as_str() {
+impl FromStr for Event {
+    type Err = EventParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
             // delegate the work to the appropriate module
-            test_str if test_str.starts_with("VpinDialog::") => {
-                Event::VpinDialog(VpinDialog::from_qstring(qs))
-            }
-            test_str if test_str.starts_with("PackagesTree::") => {
-                Event::PackagesTree(PackagesTree::from_qstring(qs))
-            }
-            test_str if test_str.starts_with("PackageWiths::") => {
-                Event::PackageWiths(PackageWiths::from_qstring(qs))
-            }
-            test_str if test_str.starts_with("MainToolbar::") => {
-                Event::MainToolbar(MainToolbar::from_qstring(qs))
+            s if s.starts_with("VpinDialog::") => s.parse().map(Event::VpinDialog),
+            s if s.starts_with("PackagesTree::") => s.parse().map(Event::PackagesTree),
+            s if s.starts_with("PackageWiths::") => s.parse().map(Event::PackageWiths),
+            s if s.starts_with("MainToolbar::") => s.parse().map(Event::MainToolbar),
+            s if s.starts_with("Connection::") => s.parse().map(Event::Connection),
+            "Error" => Ok(Event::Error),
+            "Stats" => Ok(Event::Stats),
+            // custom families are only known to the event handler registry. See
+            // Event::parse_with
+            _ => Err(EventParseError::new(s)),
+        }
+    }
+}
+
+impl Event {
+    /// Parse `s`, accepting Event::Custom for the families the application has
+    /// registered. A misspelled built-in family, or any other unregistered one,
+    /// remains an error.
+    ///
+    /// # Arguments
+    /// * `s` - The string to parse, eg "Shots::UpdateShots"
+    /// * `registered` - Whether a handler is registered for the named family
+    ///
+    /// # Returns
+    /// * The Event named by `s`, or an EventParseError
+    pub fn parse_with<F>(s: &str, registered: F) -> Result<Self, EventParseError>
+    where
+        F: Fn(&str) -> bool,
+    {
+        s.parse().or_else(|err| match custom_family(s) {
+            Some(family) if !is_built_in(family) && registered(family) => {
+                Ok(Event::Custom(s.to_string()))
             }
-            "Error" => Event::Error,
-            _ => panic!("Unable to convert to Event"),
+            _ => Err(err),
+        })
+    }
+}
+
+// the family of `s`, if it names a message of a custom family, eg "Shots" for
+// "Shots::UpdateShots"
+fn custom_family(s: &str) -> Option<&str> {
+    match s.find("::") {
+        Some(idx) if idx > 0 && idx + 2 < s.len() && !s.contains('#') => Some(&s[..idx]),
+        _ => None,
+    }
+}
+
+// whether `family` names one of the families defined by this crate, which may
+// not be extended with custom messages
+fn is_built_in(family: &str) -> bool {
+    matches!(
+        family,
+        "VpinDialog" | "PackagesTree" | "PackageWiths" | "MainToolbar" | "Connection"
+    )
+}
+
+impl TryFrom<&str> for Event {
+    type Error = EventParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Events are not FromQString, as it cannot fail, and a bad or version skewed
+/// signal string is no reason to take down the ui thread. Convert the QString
+/// with `try_from` instead, which returns an EventParseError.
+#[cfg(feature = "qt")]
+impl TryFrom<Ref<QString>> for Event {
+    type Error = EventParseError;
+
+    fn try_from(qs: Ref<QString>) -> Result<Self, Self::Error> {
+        qs.to_std_string().parse()
+    }
+}
+
+/// Returned when a string does not name an Event
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct EventParseError {
+    input: String,
+}
+
+impl EventParseError {
+    /// New up an EventParseError
+    ///
+    /// # Arguments
+    /// * `input` - The string which could not be parsed
+    pub fn new<I: Into<String>>(input: I) -> Self {
+        Self {
+            input: input.into(),
+        }
+    }
+
+    /// The string which could not be parsed
+    pub fn input(&self) -> &str {
+        self.input.as_str()
+    }
+}
+
+impl fmt::Display for EventParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unable to convert '{}' to Event", self.input)
+    }
+}
+
+impl std::error::Error for EventParseError {}
+
+/// An Event along with the sequence number of its IMsg in the Mailbox, and the
+/// id of the Request it answers, if any. This is what the secondary thread
+/// signals to the application. As a string, the sequence number follows the
+/// Event, and the request id follows the sequence number, eg
+/// "VpinDialog::UpdateRoles#42@7".
+#[derive(Debug, PartialEq)]
+pub struct Signal {
+    pub seq: Seq,
+    pub request: Option<RequestId>,
+    pub event: Event,
+}
+
+impl Signal {
+    /// New up a Signal
+    ///
+    /// # Arguments
+    /// * `seq` - The sequence number of the IMsg posted to the Mailbox
+    /// * `request` - The id of the Request being answered, if any
+    /// * `event` - The Event announcing the IMsg
+    pub fn new(seq: Seq, request: Option<RequestId>, event: Event) -> Self {
+        Self {
+            seq,
+            request,
+            event,
         }
     }
 }
 
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.event.as_str(), self.seq)?;
+        if let Some(request) = self.request {
+            write!(f, "@{}", request)?;
+        }
+        Ok(())
+    }
+}
+
+impl Signal {
+    /// Parse `s`, accepting custom Events for the families the application has
+    /// registered. See `Event::parse_with`.
+    ///
+    /// # Arguments
+    /// * `s` - The string to parse, eg "Shots::UpdateShots#42@7"
+    /// * `registered` - Whether a handler is registered for the named family
+    ///
+    /// # Returns
+    /// * The Signal named by `s`, or an EventParseError
+    pub fn parse_with<F>(s: &str, registered: F) -> Result<Self, EventParseError>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let err = || EventParseError::new(s);
+        let idx = s.rfind('#').ok_or_else(err)?;
+        let (seq, request) = match s[idx + 1..].find('@') {
+            Some(at) => {
+                let request = &s[idx + 1 + at + 1..];
+                (
+                    &s[idx + 1..idx + 1 + at],
+                    Some(request.parse().map_err(|_| err())?),
+                )
+            }
+            None => (&s[idx + 1..], None),
+        };
+        Ok(Signal {
+            seq: seq.parse().map_err(|_| err())?,
+            request,
+            event: Event::parse_with(&s[..idx], registered).map_err(|_| err())?,
+        })
+    }
+}
+
+impl FromStr for Signal {
+    type Err = EventParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Signal::parse_with(s, |_| false)
+    }
+}
+
+impl TryFrom<&str> for Signal {
+    type Error = EventParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[cfg(feature = "qt")]
+impl ToQString for Signal {
+    fn to_qstring(&self) -> CppBox<QString> {
+        QString::from_std_str(self.to_string())
+    }
+}
+
+/// As for Event, there is no FromQString. Custom events are only accepted by
+/// `Signal::parse_with`.
+#[cfg(feature = "qt")]
+impl TryFrom<Ref<QString>> for Signal {
+    type Error = EventParseError;
+
+    fn try_from(qs: Ref<QString>) -> Result<Self, Self::Error> {
+        qs.to_std_string().parse()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    #[cfg(feature = "qt")]
     use rustqt_utils::qs;
 
+    #[cfg(feature = "qt")]
     #[test]
     fn can_convert_from_event_to_qstring() {
         let event = Event::VpinDialog(VpinDialog::UpdateRoles);
@@ -80,11 +308,80 @@ mod tests {
             "VpinDialog::UpdateRoles"
         );
     }
+    #[cfg(feature = "qt")]
     #[test]
     fn can_convert_from_qstring() {
         let qstr = qs("VpinDialog::UpdateRoles");
         let qstr_ref = unsafe { qstr.as_ref() };
-        let event = Event::from_qstring(qstr_ref);
-        assert_eq!(event, Event::VpinDialog(VpinDialog::UpdateRoles));
+        let event = Event::try_from(qstr_ref);
+        assert_eq!(event, Ok(Event::VpinDialog(VpinDialog::UpdateRoles)));
+        let qstr = qs("VpinDialog::UpdateRolez");
+        let qstr_ref = unsafe { qstr.as_ref() };
+        assert_eq!(
+            Event::try_from(qstr_ref),
+            Err(EventParseError::new("VpinDialog::UpdateRolez"))
+        );
+    }
+
+    #[test]
+    fn can_parse_event() {
+        assert_eq!(
+            Event::try_from("MainToolbar::GetSites"),
+            Ok(Event::MainToolbar(MainToolbar::GetSites))
+        );
+        assert_eq!("Error".parse::<Event>(), Ok(Event::Error));
+        assert_eq!("Stats".parse::<Event>(), Ok(Event::Stats));
+    }
+
+    #[test]
+    fn can_parse_event_of_registered_family() {
+        let registered = |family: &str| family == "Shots";
+        let event = Event::parse_with("Shots::UpdateShots", registered).unwrap();
+        assert_eq!(event, Event::Custom("Shots::UpdateShots".to_string()));
+        assert_eq!(event.family(), "Shots");
+        assert!(Event::parse_with("Shots::", registered).is_err());
+        assert!(Event::parse_with("Assets::UpdateAssets", registered).is_err());
+        assert!("Shots::UpdateShots".parse::<Event>().is_err());
+        let signal = Signal::parse_with("Shots::UpdateShots#42@7", registered).unwrap();
+        assert_eq!(signal.event, event);
+    }
+
+    #[test]
+    fn misspelled_built_in_family_is_an_error() {
+        let err = Event::try_from("VpinDialg::UpdateRoles").unwrap_err();
+        assert_eq!(err.input(), "VpinDialg::UpdateRoles");
+        assert!(Event::parse_with("VpinDialg::UpdateRoles", |family| family == "Shots").is_err());
+        // a built-in family may not be extended, even should it be registered
+        assert!(Event::parse_with("VpinDialog::UpdateRolez", |_| true).is_err());
+        assert!(Signal::try_from("VpinDialg::UpdateRoles#1").is_err());
+    }
+
+    #[test]
+    fn parse_error_names_offending_string() {
+        let err = Event::try_from("VpinDialog::UpdateRolez").unwrap_err();
+        assert_eq!(err.input(), "VpinDialog::UpdateRolez");
+        assert_eq!("Bogus".parse::<Event>(), Err(EventParseError::new("Bogus")));
+    }
+
+    #[test]
+    fn can_round_trip_signal() {
+        let signal = Signal::new(42, None, Event::VpinDialog(VpinDialog::UpdateRoles));
+        assert_eq!(signal.to_string(), "VpinDialog::UpdateRoles#42");
+        assert_eq!(Signal::try_from("VpinDialog::UpdateRoles#42"), Ok(signal));
+        let signal = Signal::new(
+            42,
+            Some(RequestId::from(7)),
+            Event::VpinDialog(VpinDialog::UpdateRoles),
+        );
+        assert_eq!(signal.to_string(), "VpinDialog::UpdateRoles#42@7");
+        assert_eq!(Signal::try_from("VpinDialog::UpdateRoles#42@7"), Ok(signal));
+    }
+
+    #[test]
+    fn signal_requires_sequence_number() {
+        assert!(Signal::try_from("VpinDialog::UpdateRoles").is_err());
+        assert!(Signal::try_from("VpinDialog::UpdateRoles#x").is_err());
+        assert!(Signal::try_from("VpinDialog::UpdateRoles#1@").is_err());
+        assert!(Signal::try_from("VpinDialog::Bogus#1").is_err());
     }
 }
@@ -1,60 +1,98 @@
-use crate::{prelude::*, Event, IMsg, IVpinDialog, VpinDialog};
-use crossbeam_channel::Receiver;
+//! The event handler updates the ui in response to the replies of the secondary
+//! thread, handing each to the widget of its family. The handlers are generic
+//! over the sinks the widgets implement, so need not be pbgui widgets.
+#[cfg(feature = "qt")]
+use crate::Mailbox;
+use crate::{IMsg, IVpinDialog, InstanceId, VpinDialog};
 use log;
+#[cfg(feature = "qt")]
 use pbgui_toolbar::toolbar::MainToolbar;
+#[cfg(feature = "qt")]
 use pbgui_tree::tree;
+#[cfg(feature = "qt")]
 use pbgui_vpin::vpin_dialog;
+#[cfg(feature = "qt")]
 use pbgui_withs::WithsList;
-use qt_core::{QString, SlotOfQString};
-use qt_widgets::cpp_core::Ref;
+#[cfg(feature = "qt")]
+use qt_core::SlotOfQString;
+#[cfg(feature = "qt")]
 use std::cell::RefCell;
+#[cfg(feature = "qt")]
 use std::rc::Rc;
 
-pub mod vpin_dialog_eh;
-use vpin_dialog_eh::match_vpin_dialog;
+#[cfg(feature = "qt")]
+pub mod builder;
+pub mod connection_eh;
+pub mod error_eh;
 pub mod main_toolbar_eh;
 pub mod package_withs_eh;
 pub mod packages_tree_eh;
-use main_toolbar_eh::match_main_toolbar;
-use package_withs_eh::match_package_withs;
-use packages_tree_eh::match_packages_tree;
-/// Generate a new event handler, which is of type `SlotOfQString`.
-/// The event handler is responsible for handling Signals of type Event
+#[cfg(feature = "qt")]
+pub mod registry;
+pub mod sink;
+pub mod vpin_dialog_eh;
+#[cfg(feature = "qt")]
+pub use builder::EventHandlerBuilder;
+pub use error_eh::{ErrorCallback, ErrorCallbacks};
+#[cfg(feature = "qt")]
+pub use registry::{EventHandler, EventHandlers};
+pub use sink::{
+    CurrentSiteSink, LevelsSink, PackagesSink, PlatformsSink, RolesSink, ShowsSink, SitesSink,
+};
+
+/// Generate a new event handler, which is of type `SlotOfQString`, bound to
+/// each of the built-in widgets. See `EventHandlerBuilder` to bind only some,
+/// or several of the same kind.
+///
+/// Errors are routed to the callback registered in `errors` for the instance
+/// the failed request was made on behalf of, else for its family, and logged if
+/// there is neither.
 ///
 /// # Arguments
 /// * `dialog` - Rc wrapped VpinDialog
-/// * `receiver` - The Receiver of messages from the non-ui thread
+/// * `mailbox` - Holds the messages posted by the non-ui thread
+/// * `errors` - Notified of failed requests, by family
 ///
 /// # Returns
 /// * Slot which processes messages from the non-ui thread and updates the ui in response
+#[cfg(feature = "qt")]
 pub fn new_event_handler<'a>(
     dialog: Rc<vpin_dialog::VpinDialog<'a>>,
     tree: Rc<RefCell<tree::DistributionTreeView<'a>>>,
     withs: Rc<RefCell<WithsList<'a>>>,
     main_toolbar: Rc<MainToolbar>,
-    receiver: Receiver<IMsg>,
+    mailbox: Mailbox,
+    errors: ErrorCallbacks<'a>,
+) -> SlotOfQString<'a> {
+    new_event_handler_with(
+        dialog,
+        tree,
+        withs,
+        main_toolbar,
+        mailbox,
+        errors,
+        EventHandlers::new(),
+    )
+}
+
+/// As `new_event_handler`, also handing replies to `handlers`. A handler
+/// registered for a built-in family replaces the built-in one.
+#[cfg(feature = "qt")]
+pub fn new_event_handler_with<'a>(
+    dialog: Rc<vpin_dialog::VpinDialog<'a>>,
+    tree: Rc<RefCell<tree::DistributionTreeView<'a>>>,
+    withs: Rc<RefCell<WithsList<'a>>>,
+    main_toolbar: Rc<MainToolbar>,
+    mailbox: Mailbox,
+    errors: ErrorCallbacks<'a>,
+    handlers: EventHandlers<'a>,
 ) -> SlotOfQString<'a> {
-    SlotOfQString::new(move |name: Ref<QString>| match Event::from_qstring(name) {
-        //
-        Event::VpinDialog(vpin_dialog_event) => {
-            match_vpin_dialog(vpin_dialog_event, dialog.clone(), &receiver)
-        }
-        Event::PackagesTree(packages_tree_event) => {
-            match_packages_tree(packages_tree_event, tree.clone(), &receiver)
-        }
-        Event::PackageWiths(package_withs_event) => {
-            match_package_withs(package_withs_event, withs.clone(), &receiver)
-        }
-        Event::MainToolbar(main_toolbar_event) => {
-            match_main_toolbar(main_toolbar_event, main_toolbar.clone(), &receiver)
-        }
-        //
-        Event::Error => {
-            if let Ok(IMsg::Error(error)) = receiver.recv() {
-                log::error!("{}", error);
-            } else {
-                log::error!("unable to transmit error");
-            }
-        }
-    })
+    EventHandlerBuilder::new(mailbox)
+        .vpin_dialog(dialog)
+        .packages_tree(tree)
+        .package_withs(withs)
+        .main_toolbar(main_toolbar)
+        .errors(errors)
+        .handlers(handlers)
+        .build()
 }
@@ -0,0 +1,425 @@
+//! Builds the event handler, binding only the widgets the application has.
+use super::{
+    connection_eh::match_connection, main_toolbar_eh::match_main_toolbar,
+    package_withs_eh::match_package_withs, packages_tree_eh::match_packages_tree,
+    vpin_dialog_eh::match_vpin_dialog, *,
+};
+use crate::{outgoing::*, prelude::*, Event, InstanceId, Signal};
+use qt_core::QString;
+use qt_widgets::cpp_core::Ref;
+
+/// Builds the event handler slot. Each widget is optional, and may be of any
+/// type implementing the sinks of its family, eg a VpinDialog takes roles, sites
+/// and levels. Events of a family without a widget, or a handler, are logged and
+/// dropped. Several widgets of the same kind may be bound, each under its own
+/// InstanceId, to receive the replies to the requests made on its behalf. See
+/// `Request::for_instance`.
+///
+/// Whatever the order of the calls, a reply addressed to an instance goes to the
+/// handler bound to that instance, else to the family-wide one. Where a widget
+/// and one of `handlers` are bound to the same family and instance, the latter
+/// wins.
+///
+/// # Example
+/// ```ignore
+/// let app_update = EventHandlerBuilder::new(mailbox.clone())
+///     .main_toolbar(toolbar.clone())
+///     .errors(ErrorCallbacks::new().on_main_toolbar(|error| eprintln!("{}", error)))
+///     .build();
+/// ```
+pub struct EventHandlerBuilder<'a> {
+    mailbox: Mailbox,
+    errors: ErrorCallbacks<'a>,
+    // the handlers of the bound widgets
+    widgets: EventHandlers<'a>,
+    // the handlers of the application, which replace the widgets'
+    handlers: EventHandlers<'a>,
+}
+
+impl<'a> EventHandlerBuilder<'a> {
+    /// New up an EventHandlerBuilder without any widgets. Changes in the state
+    /// of the connection are logged.
+    ///
+    /// # Arguments
+    /// * `mailbox` - Holds the messages posted by the non-ui thread
+    pub fn new(mailbox: Mailbox) -> Self {
+        let widgets = EventHandlers::new().register(
+            OConnection::FAMILY,
+            |event, payload, instance, errors| {
+                if let Event::Connection(event) = event {
+                    match_connection(event, payload, instance, errors)
+                }
+            },
+        );
+        Self {
+            mailbox,
+            errors: ErrorCallbacks::new(),
+            widgets,
+            handlers: EventHandlers::new(),
+        }
+    }
+
+    /// Bind the VpinDialog
+    pub fn vpin_dialog<D>(self, dialog: Rc<D>) -> Self
+    where
+        D: RolesSink + SitesSink + LevelsSink + 'a,
+    {
+        self.bind(OVpinDialog::FAMILY, None, vpin_dialog_handler(dialog))
+    }
+
+    /// Bind a VpinDialog, as `instance`
+    pub fn vpin_dialog_instance<D>(self, instance: InstanceId, dialog: Rc<D>) -> Self
+    where
+        D: RolesSink + SitesSink + LevelsSink + 'a,
+    {
+        self.bind(
+            OVpinDialog::FAMILY,
+            Some(instance),
+            vpin_dialog_handler(dialog),
+        )
+    }
+
+    /// Bind the packages DistributionTreeView
+    pub fn packages_tree<T>(self, tree: Rc<T>) -> Self
+    where
+        T: PackagesSink + CurrentSiteSink + 'a,
+    {
+        self.bind(OPackagesTree::FAMILY, None, packages_tree_handler(tree))
+    }
+
+    /// Bind a packages DistributionTreeView, as `instance`
+    pub fn packages_tree_instance<T>(self, instance: InstanceId, tree: Rc<T>) -> Self
+    where
+        T: PackagesSink + CurrentSiteSink + 'a,
+    {
+        self.bind(
+            OPackagesTree::FAMILY,
+            Some(instance),
+            packages_tree_handler(tree),
+        )
+    }
+
+    /// Bind the WithsList
+    pub fn package_withs<W>(self, withs: Rc<W>) -> Self
+    where
+        W: PackagesSink + 'a,
+    {
+        self.bind(OPackageWiths::FAMILY, None, package_withs_handler(withs))
+    }
+
+    /// Bind a WithsList, as `instance`
+    pub fn package_withs_instance<W>(self, instance: InstanceId, withs: Rc<W>) -> Self
+    where
+        W: PackagesSink + 'a,
+    {
+        self.bind(
+            OPackageWiths::FAMILY,
+            Some(instance),
+            package_withs_handler(withs),
+        )
+    }
+
+    /// Bind the MainToolbar
+    pub fn main_toolbar<T>(self, toolbar: Rc<T>) -> Self
+    where
+        T: ShowsSink + RolesSink + PlatformsSink + SitesSink + 'a,
+    {
+        self.bind(OMainToolbar::FAMILY, None, main_toolbar_handler(toolbar))
+    }
+
+    /// Bind a MainToolbar, as `instance`
+    pub fn main_toolbar_instance<T>(self, instance: InstanceId, toolbar: Rc<T>) -> Self
+    where
+        T: ShowsSink + RolesSink + PlatformsSink + SitesSink + 'a,
+    {
+        self.bind(
+            OMainToolbar::FAMILY,
+            Some(instance),
+            main_toolbar_handler(toolbar),
+        )
+    }
+
+    /// Notify `errors` of failed requests, by family and instance
+    pub fn errors(mut self, errors: ErrorCallbacks<'a>) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    /// Also hand replies to `handlers`, eg those of the application's own
+    /// families. A handler registered for a built-in family replaces the widget
+    /// bound to the same instance, or the family-wide one, whether the widget is
+    /// bound before or after. It does not replace a widget bound to another
+    /// instance. Should several calls register the same family and instance, the
+    /// last wins.
+    pub fn handlers(mut self, handlers: EventHandlers<'a>) -> Self {
+        self.handlers.extend(handlers);
+        self
+    }
+
+    fn bind(
+        mut self,
+        family: &'static str,
+        instance: Option<InstanceId>,
+        handler: impl Fn(Event, IMsg, Option<InstanceId>, &ErrorCallbacks<'a>) + 'a,
+    ) -> Self {
+        self.widgets = match instance {
+            Some(instance) => self.widgets.register_instance(family, instance, handler),
+            None => self.widgets.register(family, handler),
+        };
+        self
+    }
+
+    // the handlers of the widgets replaced by the application's own, along with
+    // the mailbox and the error callbacks
+    fn into_dispatcher(self) -> Dispatcher<'a> {
+        let Self {
+            mailbox,
+            errors,
+            mut widgets,
+            handlers,
+        } = self;
+        widgets.extend(handlers);
+        Dispatcher {
+            mailbox,
+            errors,
+            handlers: widgets,
+        }
+    }
+
+    /// Generate the event handler. It handles the Signals raised by the non-ui
+    /// thread, each of which names an Event along with the sequence number of
+    /// its IMsg, which is collected from the mailbox. Signals which cannot be
+    /// parsed, including those of custom families without a handler, or whose
+    /// IMsg is missing or does not match the Event, are logged and skipped.
+    ///
+    /// Replies to requests which have been superseded since, by a later request
+    /// of the same kind made on behalf of the same instance, are dropped, so that
+    /// eg the levels of a show the user has moved on from are not shown. See
+    /// `Mailbox::is_superseded`.
+    ///
+    /// # Returns
+    /// * Slot which processes messages from the non-ui thread and updates the ui in response
+    pub fn build(self) -> SlotOfQString<'a> {
+        let dispatcher = self.into_dispatcher();
+        SlotOfQString::new(move |name: Ref<QString>| dispatcher.dispatch(&name.to_std_string()))
+    }
+}
+
+// hands each signal's IMsg to the handler of its family
+struct Dispatcher<'a> {
+    mailbox: Mailbox,
+    errors: ErrorCallbacks<'a>,
+    handlers: EventHandlers<'a>,
+}
+
+impl<'a> Dispatcher<'a> {
+    fn dispatch(&self, name: &str) {
+        // custom events are only accepted for the families registered here
+        let signal = match Signal::parse_with(name, |family| self.handlers.contains(family)) {
+            Ok(signal) => signal,
+            Err(err) => {
+                // an unknown signal is a bug, but not one worth taking down the ui for
+                log::error!("Skipping signal. {}", err);
+                return;
+            }
+        };
+        let reply = match self.mailbox.collect(&signal) {
+            Ok(reply) => reply,
+            Err(err) => {
+                log::error!("Skipping signal {}. {}", name, err);
+                return;
+            }
+        };
+        if self.mailbox.is_superseded(&reply) {
+            log::debug!("Skipping signal {}. Its request has been superseded", name);
+            return;
+        }
+        match signal.event {
+            Event::Error => {
+                if let IMsg::Error(error) = reply.msg {
+                    self.errors.report(&error, reply.instance);
+                }
+            }
+            Event::Stats => {
+                if let IMsg::Stats(stats) = reply.msg {
+                    log::info!("Query statistics:\n{}", stats);
+                }
+            }
+            // the rest belong to a family, and are handed to its handler
+            event => self
+                .handlers
+                .handle(event, reply.msg, reply.instance, &self.errors),
+        }
+    }
+}
+
+fn vpin_dialog_handler<'a, D>(
+    dialog: Rc<D>,
+) -> impl Fn(Event, IMsg, Option<InstanceId>, &ErrorCallbacks<'a>) + 'a
+where
+    D: RolesSink + SitesSink + LevelsSink + 'a,
+{
+    move |event, payload, instance, errors| {
+        if let Event::VpinDialog(event) = event {
+            match_vpin_dialog(event, &*dialog, payload, instance, errors)
+        }
+    }
+}
+
+fn packages_tree_handler<'a, T>(
+    tree: Rc<T>,
+) -> impl Fn(Event, IMsg, Option<InstanceId>, &ErrorCallbacks<'a>) + 'a
+where
+    T: PackagesSink + CurrentSiteSink + 'a,
+{
+    move |event, payload, instance, errors| {
+        if let Event::PackagesTree(event) = event {
+            match_packages_tree(event, &*tree, payload, instance, errors)
+        }
+    }
+}
+
+fn package_withs_handler<'a, W>(
+    withs: Rc<W>,
+) -> impl Fn(Event, IMsg, Option<InstanceId>, &ErrorCallbacks<'a>) + 'a
+where
+    W: PackagesSink + 'a,
+{
+    move |event, payload, instance, errors| {
+        if let Event::PackageWiths(event) = event {
+            match_package_withs(event, &*withs, payload, instance, errors)
+        }
+    }
+}
+
+fn main_toolbar_handler<'a, T>(
+    toolbar: Rc<T>,
+) -> impl Fn(Event, IMsg, Option<InstanceId>, &ErrorCallbacks<'a>) + 'a
+where
+    T: ShowsSink + RolesSink + PlatformsSink + SitesSink + 'a,
+{
+    move |event, payload, instance, errors| {
+        if let Event::MainToolbar(event) = event {
+            match_main_toolbar(event, &*toolbar, payload, instance, errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{event_handler::sink::Recorder, incoming::LevelMap, ErrorCategory, IError};
+    use std::cell::RefCell;
+
+    fn roles(handlers: &EventHandlers, instance: Option<InstanceId>) {
+        let payload = IMsg::VpinDialog(IVpinDialog::Roles(vec!["anim".into()]));
+        let event = Event::VpinDialog(VpinDialog::UpdateRoles);
+        handlers.handle(event, payload, instance, &ErrorCallbacks::new());
+    }
+
+    #[test]
+    fn instance_bindings_win_over_family_handlers_in_either_order() {
+        for widgets_first in &[true, false] {
+            let dialog = Rc::new(Recorder::default());
+            let other = Rc::new(Recorder::default());
+            let app = Rc::new(Recorder::default());
+            let bind = |builder: EventHandlerBuilder<'static>| {
+                builder
+                    .vpin_dialog(other.clone())
+                    .vpin_dialog_instance(InstanceId::from(1), dialog.clone())
+            };
+            let handlers = EventHandlers::new()
+                .register(OVpinDialog::FAMILY, vpin_dialog_handler(app.clone()));
+            let builder = EventHandlerBuilder::new(Mailbox::new());
+            let builder = if *widgets_first {
+                bind(builder).handlers(handlers)
+            } else {
+                bind(builder.handlers(handlers))
+            };
+            let handlers = builder.into_dispatcher().handlers;
+            roles(&handlers, Some(InstanceId::from(1)));
+            roles(&handlers, Some(InstanceId::from(2)));
+            roles(&handlers, None);
+            assert_eq!(dialog.calls(), vec!["roles: anim"]);
+            // the application's family-wide handler replaces the family-wide widget
+            assert_eq!(app.calls(), vec!["roles: anim", "roles: anim"]);
+            assert!(other.calls().is_empty());
+        }
+    }
+
+    #[test]
+    fn replies_to_superseded_requests_are_dropped() {
+        let dialog = Rc::new(Recorder::default());
+        let mailbox = Mailbox::new();
+        let dispatcher = EventHandlerBuilder::new(mailbox.clone())
+            .vpin_dialog(dialog.clone())
+            .into_dispatcher();
+        let requests = ["dev01", "dev02"]
+            .iter()
+            .map(|show| OVpinDialog::GetLevels(show.to_string()).to_request())
+            .collect::<Vec<_>>();
+        // the second request is made while the first is being answered
+        for request in &requests {
+            mailbox.track(request);
+        }
+        for (request, level) in requests.iter().zip(&["dev01.rd", "dev02.rd"]) {
+            let mut levels = LevelMap::new();
+            levels.insert(level.to_string(), Vec::new());
+            mailbox.address(request);
+            let seq = mailbox.post(Some(request.id), IVpinDialog::Levels(levels).to_imsg());
+            mailbox.release(request.id);
+            let event = Event::VpinDialog(VpinDialog::UpdateLevels);
+            dispatcher.dispatch(&Signal::new(seq, Some(request.id), event).to_string());
+        }
+        assert_eq!(dialog.calls(), vec!["levels: dev02.rd"]);
+    }
+
+    #[test]
+    fn errors_are_reported_to_the_callback_of_their_instance() {
+        let reported = Rc::new(RefCell::new(Vec::new()));
+        let report = |name: &'static str| {
+            let reported = reported.clone();
+            move |error: &IError| {
+                reported
+                    .borrow_mut()
+                    .push(format!("{}: {}", name, error.message))
+            }
+        };
+        let mailbox = Mailbox::new();
+        let dispatcher = EventHandlerBuilder::new(mailbox.clone())
+            .vpin_dialog_instance(InstanceId::from(1), Rc::new(Recorder::default()))
+            .vpin_dialog_instance(InstanceId::from(2), Rc::new(Recorder::default()))
+            .errors(
+                ErrorCallbacks::new()
+                    .on_instance(OVpinDialog::FAMILY, InstanceId::from(1), report("first"))
+                    .on_instance(OVpinDialog::FAMILY, InstanceId::from(2), report("second")),
+            )
+            .into_dispatcher();
+        for (instance, show) in &[(2, "dev02"), (1, "dev01")] {
+            let request = OVpinDialog::GetLevels(show.to_string())
+                .to_request_for(InstanceId::from(*instance));
+            let error = IError::new(OVpinDialog::FAMILY, ErrorCategory::Query, *show)
+                .with_request(request.id);
+            mailbox.address(&request);
+            // failures are signaled both as an Event of the family and as Event::Error
+            let seq = mailbox.post(
+                Some(request.id),
+                IVpinDialog::Error(error.clone()).to_imsg(),
+            );
+            let event = Event::VpinDialog(VpinDialog::Error);
+            dispatcher.dispatch(&Signal::new(seq, Some(request.id), event).to_string());
+            let seq = mailbox.post(Some(request.id), IMsg::Error(error));
+            dispatcher.dispatch(&Signal::new(seq, Some(request.id), Event::Error).to_string());
+            mailbox.release(request.id);
+        }
+        assert_eq!(
+            *reported.borrow(),
+            vec![
+                "second: dev02",
+                "second: dev02",
+                "first: dev01",
+                "first: dev01"
+            ]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use super::*;
+use crate::{event::Connection, IConnection};
+
+pub fn match_connection(
+    event: Connection,
+    payload: IMsg,
+    instance: Option<InstanceId>,
+    errors: &ErrorCallbacks,
+) {
+    match event {
+        Connection::Connecting => {
+            if let IMsg::Connection(IConnection::Connecting) = payload {
+                log::info!("Connecting to database");
+            } else {
+                log::error!("Connection::Connecting IMsg does not match event state");
+            }
+        }
+        Connection::Connected => {
+            if let IMsg::Connection(IConnection::Connected) = payload {
+                log::info!("Connected to database");
+            } else {
+                log::error!("Connection::Connected IMsg does not match event state");
+            }
+        }
+        Connection::Disconnected => {
+            if let IMsg::Connection(IConnection::Disconnected(reason)) = payload {
+                log::warn!("Disconnected from database: {}", reason);
+            } else {
+                log::error!("Connection::Disconnected IMsg does not match event state");
+            }
+        }
+        Connection::Retrying => {
+            if let IMsg::Connection(IConnection::Retrying {
+                attempt,
+                delay,
+                error,
+            }) = payload
+            {
+                log::warn!(
+                    "Connection attempt {} failed. Retrying in {:?}: {}",
+                    attempt,
+                    delay,
+                    error
+                );
+            } else {
+                log::error!("Connection::Retrying IMsg does not match event state");
+            }
+        }
+
+        Connection::Ping => {
+            if let IMsg::Connection(IConnection::Pong(latency)) = payload {
+                log::info!("Database round trip: {:?}", latency);
+            } else {
+                log::error!("Connection::Ping IMsg does not match event state");
+            }
+        }
+        Connection::GetServerInfo => {
+            if let IMsg::Connection(IConnection::ServerInfo(info)) = payload {
+                log::info!(
+                    "Connected to PostgreSQL {} at {}:{}/{} (schema: {})",
+                    info.version,
+                    info.host,
+                    info.port,
+                    info.dbname,
+                    info.schema.as_ref().map(String::as_str).unwrap_or("none")
+                );
+            } else {
+                log::error!("Connection::GetServerInfo IMsg does not match event state");
+            }
+        }
+        Connection::GetCurrentUser => {
+            if let IMsg::Connection(IConnection::CurrentUser(user)) = payload {
+                log::info!("Connected to database as {}", user);
+            } else {
+                log::error!("Connection::GetCurrentUser IMsg does not match event state");
+            }
+        }
+        Connection::Error => {
+            if let IMsg::Connection(IConnection::Error(error)) = payload {
+                errors.report(&error, instance);
+            } else {
+                log::error!("Connection::Error IMsg does not match event state");
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Routes the errors reported by the secondary thread to the widget whose request failed
+use crate::{outgoing::*, IError, InstanceId};
+use std::collections::HashMap;
+
+/// Notified when a request made on behalf of a widget fails
+pub type ErrorCallback<'a> = Box<dyn Fn(&IError) + 'a>;
+
+/// The callbacks notified when a request made on behalf of a widget fails, eg
+/// to clear data which is now stale. Where several widgets of a family are
+/// bound, each may have a callback of its own, keyed by its InstanceId. Errors
+/// of an instance without a callback go to that of its family, and errors of a
+/// family without a callback are logged.
+#[derive(Default)]
+pub struct ErrorCallbacks<'a> {
+    callbacks: HashMap<(&'static str, Option<InstanceId>), ErrorCallback<'a>>,
+}
+
+impl<'a> ErrorCallbacks<'a> {
+    /// New up an ErrorCallbacks which logs every error
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Notify `callback` of errors on behalf of the VpinDialog
+    pub fn on_vpin_dialog<F: Fn(&IError) + 'a>(self, callback: F) -> Self {
+        self.on(OVpinDialog::FAMILY, callback)
+    }
+
+    /// Notify `callback` of errors on behalf of the packages DistributionTreeView
+    pub fn on_packages_tree<F: Fn(&IError) + 'a>(self, callback: F) -> Self {
+        self.on(OPackagesTree::FAMILY, callback)
+    }
+
+    /// Notify `callback` of errors on behalf of the WithsList
+    pub fn on_package_withs<F: Fn(&IError) + 'a>(self, callback: F) -> Self {
+        self.on(OPackageWiths::FAMILY, callback)
+    }
+
+    /// Notify `callback` of errors on behalf of the MainToolbar
+    pub fn on_main_toolbar<F: Fn(&IError) + 'a>(self, callback: F) -> Self {
+        self.on(OMainToolbar::FAMILY, callback)
+    }
+
+    /// Notify `callback` of failed health checks
+    pub fn on_connection<F: Fn(&IError) + 'a>(self, callback: F) -> Self {
+        self.on(OConnection::FAMILY, callback)
+    }
+
+    /// Notify `callback` of errors on behalf of `family`, eg a family registered
+    /// by the application
+    pub fn on<F: Fn(&IError) + 'a>(self, family: &'static str, callback: F) -> Self {
+        self.insert(family, None, Box::new(callback))
+    }
+
+    /// Notify `callback` of errors on behalf of `instance` of `family`, in place
+    /// of the family's callback
+    ///
+    /// # Arguments
+    /// * `family` - The name of the family, eg "VpinDialog"
+    /// * `instance` - The widget the requests were made on behalf of. See
+    ///                `Request::for_instance`.
+    /// * `callback` - Notified of each failed request of `instance`
+    pub fn on_instance<F: Fn(&IError) + 'a>(
+        self,
+        family: &'static str,
+        instance: InstanceId,
+        callback: F,
+    ) -> Self {
+        self.insert(family, Some(instance), Box::new(callback))
+    }
+
+    fn insert(
+        mut self,
+        family: &'static str,
+        instance: Option<InstanceId>,
+        callback: ErrorCallback<'a>,
+    ) -> Self {
+        self.callbacks.insert((family, instance), callback);
+        self
+    }
+
+    /// Hand `error` to the callback of `instance` of its family, else to that of
+    /// its family, or log it if there is neither
+    ///
+    /// # Arguments
+    /// * `error` - The failure of a request
+    /// * `instance` - The instance the request was made on behalf of, if any
+    pub fn report(&self, error: &IError, instance: Option<InstanceId>) {
+        let callback = instance
+            .and_then(|instance| self.callbacks.get(&(error.family, Some(instance))))
+            .or_else(|| self.callbacks.get(&(error.family, None)));
+        match callback {
+            Some(callback) => callback(error),
+            None => log::error!("{}", error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorCategory;
+    use std::cell::RefCell;
+
+    #[test]
+    fn errors_go_to_the_callback_of_their_instance_else_of_their_family() {
+        let reported = RefCell::new(Vec::new());
+        let errors = ErrorCallbacks::new()
+            .on_vpin_dialog(|error| {
+                reported
+                    .borrow_mut()
+                    .push(format!("family: {}", error.message))
+            })
+            .on_instance(OVpinDialog::FAMILY, InstanceId::from(1), |error| {
+                reported.borrow_mut().push(format!("1: {}", error.message))
+            })
+            .on_instance(OVpinDialog::FAMILY, InstanceId::from(2), |error| {
+                reported.borrow_mut().push(format!("2: {}", error.message))
+            });
+        for (instance, show) in &[
+            (Some(1), "dev01"),
+            (Some(2), "dev02"),
+            (Some(3), "dev03"),
+            (None, "dev04"),
+        ] {
+            let error = IError::new(OVpinDialog::FAMILY, ErrorCategory::Query, *show);
+            errors.report(&error, instance.map(InstanceId::from));
+        }
+        assert_eq!(
+            *reported.borrow(),
+            vec!["1: dev01", "2: dev02", "family: dev03", "family: dev04"]
+        );
+    }
+}
@@ -1,45 +1,55 @@
 use super::*;
-use crate::{event::main_toolbar::MainToolbar, incoming::imain_toolbar::IMainToolbar};
-use pbgui_toolbar::toolbar::MainToolbar as MainToolbarUiElem;
-use std::rc::Rc;
+use crate::{event::MainToolbar, incoming::IMainToolbar};
 
-pub fn match_main_toolbar(
+/// Hand the payload of a MainToolbar event to `toolbar`, reporting errors as those of `instance`
+pub fn match_main_toolbar<T>(
     event: MainToolbar,
-    toolbar: Rc<MainToolbarUiElem>,
-    receiver: &Receiver<IMsg>,
-) {
+    toolbar: &T,
+    payload: IMsg,
+    instance: Option<InstanceId>,
+    errors: &ErrorCallbacks,
+) where
+    T: ShowsSink + RolesSink + PlatformsSink + SitesSink + ?Sized,
+{
     match event {
         MainToolbar::GetShows => {
-            if let Ok(IMsg::MainToolbar(IMainToolbar::Shows(shows))) = receiver.recv() {
+            if let IMsg::MainToolbar(IMainToolbar::Shows(shows)) = payload {
                 let shows_ref = shows.iter().map(|x| x.as_str()).collect::<Vec<_>>();
-                toolbar.set_level_items(shows_ref);
+                toolbar.set_shows(shows_ref);
             } else {
                 log::error!("MainToolbar::GetShows IMsg does not match event state");
             }
         }
         MainToolbar::GetRoles => {
-            if let Ok(IMsg::MainToolbar(IMainToolbar::Roles(roles))) = receiver.recv() {
+            if let IMsg::MainToolbar(IMainToolbar::Roles(roles)) = payload {
                 let roles_ref = roles.iter().map(|x| x.as_str()).collect::<Vec<_>>();
-                toolbar.set_role_items(roles_ref);
+                toolbar.set_roles(roles_ref);
             } else {
                 log::error!("MainToolbar::GetRoles IMsg does not match event state");
             }
         }
         MainToolbar::GetPlatforms => {
-            if let Ok(IMsg::MainToolbar(IMainToolbar::Platforms(platforms))) = receiver.recv() {
+            if let IMsg::MainToolbar(IMainToolbar::Platforms(platforms)) = payload {
                 let platforms_ref = platforms.iter().map(|x| x.as_str()).collect::<Vec<_>>();
-                toolbar.set_platform_items(platforms_ref);
+                toolbar.set_platforms(platforms_ref);
             } else {
                 log::error!("MainToolbar::GetPlatforms IMsg does not match event state");
             }
         }
         MainToolbar::GetSites => {
-            if let Ok(IMsg::MainToolbar(IMainToolbar::Sites(sites))) = receiver.recv() {
+            if let IMsg::MainToolbar(IMainToolbar::Sites(sites)) = payload {
                 let sites_ref = sites.iter().map(|x| x.as_str()).collect::<Vec<_>>();
-                toolbar.set_site_items(sites_ref);
+                toolbar.set_sites(sites_ref);
             } else {
                 log::error!("MainToolbar::GetSites IMsg does not match event state");
             }
         }
+        MainToolbar::Error => {
+            if let IMsg::MainToolbar(IMainToolbar::Error(error)) = payload {
+                errors.report(&error, instance);
+            } else {
+                log::error!("MainToolbar::Error IMsg does not match event state");
+            }
+        }
     }
 }
@@ -1,22 +1,31 @@
 use super::*;
-use crate::{event::package_withs::PackageWiths, incoming::ipackage_withs::IPackageWiths};
-use pbgui_withs::WithsList;
-use std::cell::RefCell;
-use std::rc::Rc;
+use crate::{event::PackageWiths, incoming::IPackageWiths};
 
-pub fn match_package_withs<'a>(
+/// Hand the payload of a PackageWiths event to `withs`, reporting errors as those of `instance`
+pub fn match_package_withs<W>(
     event: PackageWiths,
-    withs: Rc<RefCell<WithsList<'a>>>,
-    receiver: &Receiver<IMsg>,
-) {
+    withs: &W,
+    payload: IMsg,
+    instance: Option<InstanceId>,
+    errors: &ErrorCallbacks,
+) where
+    W: PackagesSink + ?Sized,
+{
     match event {
         PackageWiths::GetPackages => {
-            if let Ok(IMsg::PackageWiths(IPackageWiths::Packages(packages))) = receiver.recv() {
+            if let IMsg::PackageWiths(IPackageWiths::Packages(packages)) = payload {
                 let packages_ref = packages.iter().map(|x| x.as_str()).collect::<Vec<_>>();
-                withs.borrow().set_cb_items(packages_ref);
+                withs.set_packages(packages_ref);
             } else {
                 log::error!("PackagesTree::GetPackages IMsg does not match event state");
             }
         }
+        PackageWiths::Error => {
+            if let IMsg::PackageWiths(IPackageWiths::Error(error)) = payload {
+                errors.report(&error, instance);
+            } else {
+                log::error!("PackageWiths::Error IMsg does not match event state");
+            }
+        }
     }
 }
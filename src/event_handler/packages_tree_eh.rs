@@ -1,30 +1,74 @@
 use super::*;
-use crate::{event::packages_tree::PackagesTree, IPackagesTree};
-use pbgui_tree::tree;
-use std::cell::RefCell;
-use std::rc::Rc;
+use crate::{event::PackagesTree, IPackagesTree};
 
-pub fn match_packages_tree<'a>(
+/// Hand the payload of a PackagesTree event to `tree`, reporting errors as those of `instance`
+pub fn match_packages_tree<T>(
     event: PackagesTree,
-    tree: Rc<RefCell<tree::DistributionTreeView<'a>>>,
-    receiver: &Receiver<IMsg>,
-) {
+    tree: &T,
+    payload: IMsg,
+    instance: Option<InstanceId>,
+    errors: &ErrorCallbacks,
+) where
+    T: PackagesSink + CurrentSiteSink + ?Sized,
+{
     match event {
         PackagesTree::GetPackages => {
-            if let Ok(IMsg::PackagesTree(IPackagesTree::Packages(packages))) = receiver.recv() {
+            if let IMsg::PackagesTree(IPackagesTree::Packages(packages)) = payload {
                 let packages_ref = packages.iter().map(|x| x.as_str()).collect::<Vec<_>>();
-                tree.borrow().set_packages(packages_ref);
+                tree.set_packages(packages_ref);
             } else {
                 log::error!("PackagesTree::GetPackages IMsg does not match event state");
             }
         }
         PackagesTree::GetSites => {
-            if let Ok(IMsg::PackagesTree(IPackagesTree::Sites(sites))) = receiver.recv() {
+            if let IMsg::PackagesTree(IPackagesTree::Sites { sites, current }) = payload {
                 let sites_ref = sites.iter().map(|x| x.as_str()).collect::<Vec<_>>();
-                tree.borrow().set_sites(sites_ref, "portland"); // TODO: pass current site in IPackagesTree::Sites IMsg
+                tree.set_sites_with_current(sites_ref, &current);
             } else {
                 log::error!("IMsg does not have Sites")
             }
         }
+        PackagesTree::Error => {
+            if let IMsg::PackagesTree(IPackagesTree::Error(error)) = payload {
+                errors.report(&error, instance);
+            } else {
+                log::error!("PackagesTree::Error IMsg does not match event state");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_handler::sink::Recorder;
+    use std::cell::RefCell;
+
+    #[test]
+    fn a_shared_tree_is_borrowed_to_take_its_payload() {
+        let tree = RefCell::new(Recorder::default());
+        let packages = IMsg::PackagesTree(IPackagesTree::Packages(vec!["maya".into()]));
+        match_packages_tree(
+            PackagesTree::GetPackages,
+            &tree,
+            packages,
+            None,
+            &ErrorCallbacks::new(),
+        );
+        let sites = IMsg::PackagesTree(IPackagesTree::Sites {
+            sites: vec!["any".into(), "hyderabad".into()],
+            current: "hyderabad".into(),
+        });
+        match_packages_tree(
+            PackagesTree::GetSites,
+            &tree,
+            sites,
+            None,
+            &ErrorCallbacks::new(),
+        );
+        assert_eq!(
+            tree.borrow().calls(),
+            vec!["packages: maya", "sites at hyderabad: any,hyderabad"]
+        );
     }
 }
@@ -0,0 +1,112 @@
+//! The event handler hands each reply to the handler registered for its family.
+//! The built-in families are bound to their widgets by `EventHandlerBuilder`.
+//! Applications may register their own, whose replies are signaled as
+//! Event::Custom, or replace a built-in family's handler.
+//!
+//! Where there are several widgets of a family, each is registered under its
+//! InstanceId, and receives the replies to the requests made on its behalf.
+use super::*;
+use crate::{Event, InstanceId};
+use std::collections::HashMap;
+
+/// Handles the replies of a family on the ui thread, given the Event signaled,
+/// its IMsg, the instance the request was made on behalf of, and the callbacks
+/// to report errors to
+pub type EventHandler<'a> = Box<dyn Fn(Event, IMsg, Option<InstanceId>, &ErrorCallbacks<'a>) + 'a>;
+
+/// The handlers of each family, keyed by family name, then by instance
+#[derive(Default)]
+pub struct EventHandlers<'a> {
+    handlers: HashMap<&'static str, HashMap<Option<InstanceId>, EventHandler<'a>>>,
+}
+
+impl<'a> EventHandlers<'a> {
+    /// New up an EventHandlers without any handlers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hand the replies of `family` to `handler`, replacing its handler if it
+    /// has one already. Replies addressed to an instance without a handler of
+    /// its own are handed to it too.
+    ///
+    /// # Arguments
+    /// * `family` - The name of the family, eg "Shots". Replies are routed by
+    ///              `Event::family`.
+    /// * `handler` - Updates the ui in response to each reply of the family
+    pub fn register<F>(self, family: &'static str, handler: F) -> Self
+    where
+        F: Fn(Event, IMsg, Option<InstanceId>, &ErrorCallbacks<'a>) + 'a,
+    {
+        self.insert(family, None, Box::new(handler))
+    }
+
+    /// Hand the replies of `family` addressed to `instance` to `handler`,
+    /// replacing its handler if it has one already
+    pub fn register_instance<F>(
+        self,
+        family: &'static str,
+        instance: InstanceId,
+        handler: F,
+    ) -> Self
+    where
+        F: Fn(Event, IMsg, Option<InstanceId>, &ErrorCallbacks<'a>) + 'a,
+    {
+        self.insert(family, Some(instance), Box::new(handler))
+    }
+
+    fn insert(
+        mut self,
+        family: &'static str,
+        instance: Option<InstanceId>,
+        handler: EventHandler<'a>,
+    ) -> Self {
+        self.handlers
+            .entry(family)
+            .or_insert_with(HashMap::new)
+            .insert(instance, handler);
+        self
+    }
+
+    /// Whether a handler is registered for `family`, for any instance
+    pub fn contains(&self, family: &str) -> bool {
+        self.handlers.contains_key(family)
+    }
+
+    // register each of `other`'s handlers, replacing ours for the same family
+    // and instance
+    pub(crate) fn extend(&mut self, other: EventHandlers<'a>) {
+        for (family, handlers) in other.handlers {
+            self.handlers
+                .entry(family)
+                .or_insert_with(HashMap::new)
+                .extend(handlers);
+        }
+    }
+
+    /// Hand `payload` to the handler of `instance` of the family of `event`,
+    /// or else to the family's handler. Logs and drops it if there is neither.
+    pub(crate) fn handle(
+        &self,
+        event: Event,
+        payload: IMsg,
+        instance: Option<InstanceId>,
+        errors: &ErrorCallbacks<'a>,
+    ) {
+        let handler = self
+            .handlers
+            .get(event.family())
+            .and_then(|handlers| handlers.get(&instance).or_else(|| handlers.get(&None)));
+        match (handler, instance) {
+            (Some(handler), _) => handler(event, payload, instance, errors),
+            (None, Some(instance)) => log::error!(
+                "No handler is registered for {} of instance {}. Dropping",
+                event.as_str(),
+                instance
+            ),
+            (None, None) => {
+                log::error!("No handler is registered for {}. Dropping", event.as_str())
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! The sinks the event handlers hand the data they receive to. The pbgui widgets
+//! implement them, but any widget may, as may a fake which records what it is
+//! given.
+use crate::incoming::LevelMap;
+#[cfg(feature = "qt")]
+use pbgui_toolbar::toolbar::MainToolbar;
+#[cfg(feature = "qt")]
+use pbgui_tree::tree::DistributionTreeView;
+#[cfg(feature = "qt")]
+use pbgui_vpin::vpin_dialog::VpinDialog;
+#[cfg(feature = "qt")]
+use pbgui_withs::WithsList;
+use std::cell::RefCell;
+
+/// Takes the roles to choose from
+pub trait RolesSink {
+    fn set_roles(&self, roles: Vec<&str>);
+}
+
+/// Takes the sites to choose from
+pub trait SitesSink {
+    fn set_sites(&self, sites: Vec<&str>);
+}
+
+/// Takes the sites to choose from, along with the site the application is
+/// running at, to select
+pub trait CurrentSiteSink {
+    fn set_sites_with_current(&self, sites: Vec<&str>, current: &str);
+}
+
+/// Takes the levels of a show to choose from
+pub trait LevelsSink {
+    fn set_levels(&self, levels: LevelMap);
+}
+
+/// Takes the shows to choose from
+pub trait ShowsSink {
+    fn set_shows(&self, shows: Vec<&str>);
+}
+
+/// Takes the platforms to choose from
+pub trait PlatformsSink {
+    fn set_platforms(&self, platforms: Vec<&str>);
+}
+
+/// Takes the packages to choose from
+pub trait PackagesSink {
+    fn set_packages(&self, packages: Vec<&str>);
+}
+
+// a widget shared as Rc<RefCell<_>> is borrowed for the duration of the call
+impl<S: RolesSink + ?Sized> RolesSink for RefCell<S> {
+    fn set_roles(&self, roles: Vec<&str>) {
+        self.borrow().set_roles(roles)
+    }
+}
+
+impl<S: SitesSink + ?Sized> SitesSink for RefCell<S> {
+    fn set_sites(&self, sites: Vec<&str>) {
+        self.borrow().set_sites(sites)
+    }
+}
+
+impl<S: CurrentSiteSink + ?Sized> CurrentSiteSink for RefCell<S> {
+    fn set_sites_with_current(&self, sites: Vec<&str>, current: &str) {
+        self.borrow().set_sites_with_current(sites, current)
+    }
+}
+
+impl<S: LevelsSink + ?Sized> LevelsSink for RefCell<S> {
+    fn set_levels(&self, levels: LevelMap) {
+        self.borrow().set_levels(levels)
+    }
+}
+
+impl<S: ShowsSink + ?Sized> ShowsSink for RefCell<S> {
+    fn set_shows(&self, shows: Vec<&str>) {
+        self.borrow().set_shows(shows)
+    }
+}
+
+impl<S: PlatformsSink + ?Sized> PlatformsSink for RefCell<S> {
+    fn set_platforms(&self, platforms: Vec<&str>) {
+        self.borrow().set_platforms(platforms)
+    }
+}
+
+impl<S: PackagesSink + ?Sized> PackagesSink for RefCell<S> {
+    fn set_packages(&self, packages: Vec<&str>) {
+        self.borrow().set_packages(packages)
+    }
+}
+
+#[cfg(feature = "qt")]
+impl<'a> RolesSink for VpinDialog<'a> {
+    fn set_roles(&self, roles: Vec<&str>) {
+        VpinDialog::set_roles(self, roles)
+    }
+}
+
+#[cfg(feature = "qt")]
+impl<'a> SitesSink for VpinDialog<'a> {
+    fn set_sites(&self, sites: Vec<&str>) {
+        VpinDialog::set_sites(self, sites)
+    }
+}
+
+#[cfg(feature = "qt")]
+impl<'a> LevelsSink for VpinDialog<'a> {
+    fn set_levels(&self, levels: LevelMap) {
+        VpinDialog::set_levels(self, levels)
+    }
+}
+
+#[cfg(feature = "qt")]
+impl<'a> CurrentSiteSink for DistributionTreeView<'a> {
+    fn set_sites_with_current(&self, sites: Vec<&str>, current: &str) {
+        DistributionTreeView::set_sites(self, sites, current)
+    }
+}
+
+#[cfg(feature = "qt")]
+impl<'a> PackagesSink for DistributionTreeView<'a> {
+    fn set_packages(&self, packages: Vec<&str>) {
+        DistributionTreeView::set_packages(self, packages)
+    }
+}
+
+#[cfg(feature = "qt")]
+impl<'a> PackagesSink for WithsList<'a> {
+    fn set_packages(&self, packages: Vec<&str>) {
+        self.set_cb_items(packages)
+    }
+}
+
+// the toolbar's level combobox lists the shows
+#[cfg(feature = "qt")]
+impl ShowsSink for MainToolbar {
+    fn set_shows(&self, shows: Vec<&str>) {
+        self.set_level_items(shows)
+    }
+}
+
+#[cfg(feature = "qt")]
+impl RolesSink for MainToolbar {
+    fn set_roles(&self, roles: Vec<&str>) {
+        self.set_role_items(roles)
+    }
+}
+
+#[cfg(feature = "qt")]
+impl PlatformsSink for MainToolbar {
+    fn set_platforms(&self, platforms: Vec<&str>) {
+        self.set_platform_items(platforms)
+    }
+}
+
+#[cfg(feature = "qt")]
+impl SitesSink for MainToolbar {
+    fn set_sites(&self, sites: Vec<&str>) {
+        self.set_site_items(sites)
+    }
+}
+
+/// A sink which records what it is given, for testing the event handlers
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct Recorder {
+    calls: RefCell<Vec<String>>,
+}
+
+#[cfg(test)]
+impl Recorder {
+    fn record(&self, name: &str, items: Vec<&str>) {
+        self.calls
+            .borrow_mut()
+            .push(format!("{}: {}", name, items.join(",")));
+    }
+
+    /// The calls made so far, eg "roles: anim,model"
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+impl RolesSink for Recorder {
+    fn set_roles(&self, roles: Vec<&str>) {
+        self.record("roles", roles)
+    }
+}
+
+#[cfg(test)]
+impl SitesSink for Recorder {
+    fn set_sites(&self, sites: Vec<&str>) {
+        self.record("sites", sites)
+    }
+}
+
+#[cfg(test)]
+impl CurrentSiteSink for Recorder {
+    fn set_sites_with_current(&self, sites: Vec<&str>, current: &str) {
+        let name = format!("sites at {}", current);
+        self.record(&name, sites)
+    }
+}
+
+#[cfg(test)]
+impl LevelsSink for Recorder {
+    fn set_levels(&self, levels: LevelMap) {
+        let mut levels = levels.keys().map(String::as_str).collect::<Vec<_>>();
+        levels.sort();
+        self.record("levels", levels)
+    }
+}
+
+#[cfg(test)]
+impl ShowsSink for Recorder {
+    fn set_shows(&self, shows: Vec<&str>) {
+        self.record("shows", shows)
+    }
+}
+
+#[cfg(test)]
+impl PlatformsSink for Recorder {
+    fn set_platforms(&self, platforms: Vec<&str>) {
+        self.record("platforms", platforms)
+    }
+}
+
+#[cfg(test)]
+impl PackagesSink for Recorder {
+    fn set_packages(&self, packages: Vec<&str>) {
+        self.record("packages", packages)
+    }
+}
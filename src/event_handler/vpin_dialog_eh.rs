@@ -1,13 +1,18 @@
 use super::*;
 
-pub fn match_vpin_dialog<'a>(
+/// Hand the payload of a VpinDialog event to `dialog`, reporting errors as those of `instance`
+pub fn match_vpin_dialog<D>(
     event: VpinDialog,
-    dialog: Rc<vpin_dialog::VpinDialog<'a>>,
-    receiver: &Receiver<IMsg>,
-) {
+    dialog: &D,
+    payload: IMsg,
+    instance: Option<InstanceId>,
+    errors: &ErrorCallbacks,
+) where
+    D: RolesSink + SitesSink + LevelsSink + ?Sized,
+{
     match event {
         VpinDialog::UpdateSites => {
-            if let Ok(IMsg::VpinDialog(IVpinDialog::Sites(sites))) = receiver.recv() {
+            if let IMsg::VpinDialog(IVpinDialog::Sites(sites)) = payload {
                 let sites_ref = sites.iter().map(|x| x.as_str()).collect::<Vec<_>>();
                 dialog.set_sites(sites_ref);
             } else {
@@ -15,7 +20,7 @@ pub fn match_vpin_dialog<'a>(
             }
         }
         VpinDialog::UpdateRoles => {
-            if let Ok(IMsg::VpinDialog(IVpinDialog::Roles(roles))) = receiver.recv() {
+            if let IMsg::VpinDialog(IVpinDialog::Roles(roles)) = payload {
                 let roles_ref = roles.iter().map(|x| x.as_str()).collect::<Vec<_>>();
                 dialog.set_roles(roles_ref);
             } else {
@@ -23,11 +28,71 @@ pub fn match_vpin_dialog<'a>(
             }
         }
         VpinDialog::UpdateLevels => {
-            if let Ok(IMsg::VpinDialog(IVpinDialog::Levels(level_map))) = receiver.recv() {
+            if let IMsg::VpinDialog(IVpinDialog::Levels(level_map)) = payload {
                 dialog.set_levels(level_map);
             } else {
                 log::error!("IMsg does not have LevelMap");
             }
         }
+        VpinDialog::Error => {
+            if let IMsg::VpinDialog(IVpinDialog::Error(error)) = payload {
+                errors.report(&error, instance);
+            } else {
+                log::error!("VpinDialog::Error IMsg does not match event state");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{event_handler::sink::Recorder, incoming::LevelMap, ErrorCategory, IError};
+    use std::cell::Cell;
+
+    #[test]
+    fn payloads_are_handed_to_the_dialog() {
+        let dialog = Recorder::default();
+        let errors = ErrorCallbacks::new();
+        let roles = IMsg::VpinDialog(IVpinDialog::Roles(vec!["anim".into(), "model".into()]));
+        match_vpin_dialog(VpinDialog::UpdateRoles, &dialog, roles, None, &errors);
+        let mut levels = LevelMap::new();
+        levels.insert("dev01.rd".into(), vec!["dev01.rd.0001".into()]);
+        let levels = IMsg::VpinDialog(IVpinDialog::Levels(levels));
+        match_vpin_dialog(VpinDialog::UpdateLevels, &dialog, levels, None, &errors);
+        assert_eq!(
+            dialog.calls(),
+            vec!["roles: anim,model", "levels: dev01.rd"]
+        );
+    }
+
+    #[test]
+    fn mismatched_payloads_are_dropped() {
+        let dialog = Recorder::default();
+        let sites = IMsg::VpinDialog(IVpinDialog::Sites(vec!["portland".into()]));
+        match_vpin_dialog(
+            VpinDialog::UpdateRoles,
+            &dialog,
+            sites,
+            None,
+            &ErrorCallbacks::new(),
+        );
+        assert!(dialog.calls().is_empty());
+    }
+
+    #[test]
+    fn errors_are_reported_to_the_family_callback() {
+        let reported = Cell::new(0);
+        let errors = ErrorCallbacks::new().on_vpin_dialog(|_| reported.set(reported.get() + 1));
+        let error = IError::new("VpinDialog", ErrorCategory::Query, "no such show");
+        let payload = IMsg::VpinDialog(IVpinDialog::Error(error));
+        match_vpin_dialog(
+            VpinDialog::Error,
+            &Recorder::default(),
+            payload,
+            None,
+            &errors,
+        );
+        assert_eq!(reported.get(), 1);
     }
 }
@@ -0,0 +1,253 @@
+//! family defines the messages exchanged on behalf of each widget integration.
+//!
+//! A family is made up of three enums:
+//! * the request, sent from the application to the secondary thread (an OMsg)
+//! * the response, sent from the secondary thread to the application (an IMsg)
+//! * the event, signaled to qt to announce that a response is waiting
+//!
+//! Each family is declared once, via `message_family!`, and re-exported from
+//! `outgoing`, `incoming` and `event` respectively. Adding a family also requires
+//! a variant named after it in `OMsg`, `IMsg` and `Event`, and registering its
+//! handlers in `thread::registry::Handlers::new` and `new_event_handler`.
+//!
+//! Families declared outside of this crate carry their messages as `CustomMsg`s
+//! instead. See `custom`.
+
+/// Generate the request, response and event enums for a family of messages,
+/// along with their `ToOMsg`, `ToIMsg` and `ToEvent` impls, the request's
+/// `FAMILY` and `kind`, and the event's string round trip (`as_str`, `FromStr`, `ToQString`
+/// and `TryFrom<Ref<QString>>`). There is no `FromQString`, as it cannot fail.
+///
+/// The family name must match the variant wrapping the family in `OMsg`,
+/// `IMsg` and `Event`. It is also the prefix of each event's string, eg
+/// `"VpinDialog::UpdateRoles"`. Attributes, such as derives and doc comments,
+/// are passed through to the enums and their variants.
+///
+/// # Example
+/// ```ignore
+/// message_family! {
+///     family PackageWiths;
+///
+///     #[derive(Debug, PartialEq, Clone)]
+///     request OPackageWiths {
+///         GetPackages,
+///     }
+///
+///     response IPackageWiths {
+///         Packages(Vec<String>),
+///     }
+///
+///     #[derive(Debug, PartialEq)]
+///     event PackageWiths {
+///         GetPackages,
+///     }
+/// }
+/// ```
+macro_rules! message_family {
+    (
+        family $family:ident;
+
+        $(#[$request_meta:meta])*
+        request $request:ident {
+            $(
+                $(#[$request_variant_meta:meta])*
+                $request_variant:ident
+                $(( $($request_tuple:ty),* $(,)? ))?
+                $({ $($(#[$request_field_meta:meta])* $request_field:ident : $request_field_ty:ty),* $(,)? })?
+            ),* $(,)?
+        }
+
+        $(#[$response_meta:meta])*
+        response $response:ident {
+            $(
+                $(#[$response_variant_meta:meta])*
+                $response_variant:ident
+                $(( $($response_tuple:ty),* $(,)? ))?
+                $({ $($(#[$response_field_meta:meta])* $response_field:ident : $response_field_ty:ty),* $(,)? })?
+            ),* $(,)?
+        }
+
+        $(#[$event_meta:meta])*
+        event $event:ident {
+            $(
+                $(#[$event_variant_meta:meta])*
+                $event_variant:ident
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$request_meta])*
+        pub enum $request {
+            $(
+                $(#[$request_variant_meta])*
+                $request_variant
+                $(( $($request_tuple),* ))?
+                $({ $($(#[$request_field_meta])* $request_field: $request_field_ty),* })?
+            ),*
+        }
+
+        impl $request {
+            /// The name of the family, eg "VpinDialog"
+            pub const FAMILY: &'static str = stringify!($family);
+
+            /// Names the kind of request, eg "VpinDialog::GetLevels", regardless
+            /// of its arguments
+            pub fn kind(&self) -> &'static str {
+                match self {
+                    $(
+                        $request::$request_variant { .. } => {
+                            concat!(stringify!($family), "::", stringify!($request_variant))
+                        }
+                    )*
+                }
+            }
+        }
+
+        impl $crate::outgoing::ToOMsg for $request {
+            fn to_omsg(self) -> $crate::outgoing::OMsg {
+                $crate::outgoing::OMsg::$family(self)
+            }
+        }
+
+        $(#[$response_meta])*
+        pub enum $response {
+            $(
+                $(#[$response_variant_meta])*
+                $response_variant
+                $(( $($response_tuple),* ))?
+                $({ $($(#[$response_field_meta])* $response_field: $response_field_ty),* })?
+            ),*
+        }
+
+        impl $crate::incoming::ToIMsg for $response {
+            fn to_imsg(self) -> $crate::incoming::IMsg {
+                $crate::incoming::IMsg::$family(self)
+            }
+        }
+
+        $(#[$event_meta])*
+        pub enum $event {
+            $(
+                $(#[$event_variant_meta])*
+                $event_variant
+            ),*
+        }
+
+        impl $event {
+            /// The string carried by the qt signal, eg "VpinDialog::UpdateRoles"
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $(
+                        $event::$event_variant => {
+                            concat!(stringify!($family), "::", stringify!($event_variant))
+                        }
+                    )*
+                }
+            }
+        }
+
+        impl $crate::event::ToEvent for $event {
+            fn to_event(self) -> $crate::event::Event {
+                $crate::event::Event::$family(self)
+            }
+        }
+
+        impl std::str::FromStr for $event {
+            type Err = $crate::event::EventParseError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $(
+                    if s == concat!(stringify!($family), "::", stringify!($event_variant)) {
+                        return Ok($event::$event_variant);
+                    }
+                )*
+                Err($crate::event::EventParseError::new(s))
+            }
+        }
+
+        #[cfg(feature = "qt")]
+        impl qt_thread_conductor::traits::ToQString for $event {
+            fn to_qstring(&self) -> qt_widgets::cpp_core::CppBox<qt_core::QString> {
+                qt_core::QString::from_std_str(self.as_str())
+            }
+        }
+
+        #[cfg(feature = "qt")]
+        impl std::convert::TryFrom<qt_widgets::cpp_core::Ref<qt_core::QString>> for $event {
+            type Error = $crate::event::EventParseError;
+
+            fn try_from(
+                qs: qt_widgets::cpp_core::Ref<qt_core::QString>,
+            ) -> Result<Self, Self::Error> {
+                qs.to_std_string().parse()
+            }
+        }
+    };
+}
+
+pub mod connection;
+pub mod main_toolbar;
+pub mod package_withs;
+pub mod packages_tree;
+pub mod vpin_dialog;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Event, IMsg, OMsg, ToEvent, ToIMsg, ToOMsg};
+
+    #[test]
+    fn event_strings_are_prefixed_by_family() {
+        assert_eq!(
+            vpin_dialog::VpinDialog::UpdateLevels.as_str(),
+            "VpinDialog::UpdateLevels"
+        );
+        assert_eq!(
+            connection::Connection::GetServerInfo.as_str(),
+            "Connection::GetServerInfo"
+        );
+    }
+
+    #[test]
+    fn request_kind_ignores_arguments() {
+        assert_eq!(
+            vpin_dialog::OVpinDialog::GetLevels("dev01".to_string()).kind(),
+            "VpinDialog::GetLevels"
+        );
+        assert_eq!(
+            vpin_dialog::OVpinDialog::GetRoles.kind(),
+            "VpinDialog::GetRoles"
+        );
+    }
+
+    #[test]
+    fn event_strings_round_trip() {
+        for name in &[
+            "MainToolbar::GetShows",
+            "MainToolbar::GetRoles",
+            "MainToolbar::GetPlatforms",
+            "MainToolbar::GetSites",
+        ] {
+            let event: main_toolbar::MainToolbar = name.parse().unwrap();
+            assert_eq!(&event.as_str(), name);
+        }
+        assert!("MainToolbar::GetShow"
+            .parse::<main_toolbar::MainToolbar>()
+            .is_err());
+    }
+
+    #[test]
+    fn family_enums_convert_to_top_level_enums() {
+        assert_eq!(
+            package_withs::OPackageWiths::GetPackages.to_omsg(),
+            OMsg::PackageWiths(package_withs::OPackageWiths::GetPackages)
+        );
+        assert!(matches!(
+            package_withs::IPackageWiths::Packages(vec![]).to_imsg(),
+            IMsg::PackageWiths(package_withs::IPackageWiths::Packages(_))
+        ));
+        assert_eq!(
+            package_withs::PackageWiths::GetPackages.to_event(),
+            Event::PackageWiths(package_withs::PackageWiths::GetPackages)
+        );
+    }
+}
@@ -0,0 +1,58 @@
+//! Messages concerning the secondary thread's connection to the database
+use crate::incoming::IError;
+use std::time::Duration;
+
+message_family! {
+    family Connection;
+
+    #[derive(Debug, PartialEq, Clone)]
+    request OConnection {
+        Ping,
+        GetServerInfo,
+        GetCurrentUser,
+    }
+
+    /// The state of the secondary thread's connection to the database
+    response IConnection {
+        Connecting,
+        Connected,
+        /// An established connection was lost. Carries the reason.
+        Disconnected(String),
+        /// A connection attempt failed and will be retried after `delay`
+        Retrying {
+            attempt: u32,
+            delay: Duration,
+            error: String,
+        },
+        /// The round trip time of a trivial query
+        Pong(Duration),
+        ServerInfo(ServerInfo),
+        /// The role the connection is operating as
+        CurrentUser(String),
+        /// A health check failed
+        Error(IError),
+    }
+
+    #[derive(Debug, PartialEq)]
+    event Connection {
+        Connecting,
+        Connected,
+        Disconnected,
+        Retrying,
+        Ping,
+        GetServerInfo,
+        GetCurrentUser,
+        Error,
+    }
+}
+
+/// Describes the server the secondary thread is connected to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub host: String,
+    pub port: u64,
+    pub dbname: String,
+    pub version: String,
+    /// The first schema in the search path, if any
+    pub schema: Option<String>,
+}
@@ -0,0 +1,32 @@
+//! Messages on behalf of the MainToolbar
+use crate::incoming::IError;
+
+message_family! {
+    family MainToolbar;
+
+    #[derive(Debug, PartialEq, Clone)]
+    request OMainToolbar {
+        GetShows,
+        GetRoles,
+        GetPlatforms,
+        GetSites,
+    }
+
+    response IMainToolbar {
+        Shows(Vec<String>),
+        Roles(Vec<String>),
+        Platforms(Vec<String>),
+        Sites(Vec<String>),
+        /// A request made on behalf of the widget failed
+        Error(IError),
+    }
+
+    #[derive(Debug, PartialEq)]
+    event MainToolbar {
+        GetShows,
+        GetRoles,
+        GetPlatforms,
+        GetSites,
+        Error,
+    }
+}
@@ -0,0 +1,23 @@
+//! Messages on behalf of the WithsList
+use crate::incoming::IError;
+
+message_family! {
+    family PackageWiths;
+
+    #[derive(Debug, PartialEq, Clone)]
+    request OPackageWiths {
+        GetPackages,
+    }
+
+    response IPackageWiths {
+        Packages(Vec<String>),
+        /// A request made on behalf of the widget failed
+        Error(IError),
+    }
+
+    #[derive(Debug, PartialEq)]
+    event PackageWiths {
+        GetPackages,
+        Error,
+    }
+}
@@ -0,0 +1,33 @@
+//! Messages on behalf of the packages DistributionTreeView
+use crate::incoming::IError;
+
+message_family! {
+    family PackagesTree;
+
+    #[derive(Debug, PartialEq, Clone)]
+    request OPackagesTree {
+        GetPackages,
+        GetSites,
+        /// Run at the given site rather than the one worked out by the
+        /// SitePolicy. Answered with the sites, as GetSites is.
+        SetCurrentSite(String),
+    }
+
+    response IPackagesTree {
+        Packages(Vec<String>),
+        /// The sites, along with the site the application is running at
+        Sites {
+            sites: Vec<String>,
+            current: String,
+        },
+        /// A request made on behalf of the widget failed
+        Error(IError),
+    }
+
+    #[derive(Debug, PartialEq)]
+    event PackagesTree {
+        GetPackages,
+        GetSites,
+        Error,
+    }
+}
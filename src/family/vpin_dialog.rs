@@ -0,0 +1,29 @@
+//! Messages on behalf of the VpinDialog
+use crate::incoming::{IError, LevelMap};
+
+message_family! {
+    family VpinDialog;
+
+    #[derive(Debug, PartialEq, Clone)]
+    request OVpinDialog {
+        GetSites,
+        GetRoles,
+        GetLevels(String),
+    }
+
+    response IVpinDialog {
+        Roles(Vec<String>),
+        Sites(Vec<String>),
+        Levels(LevelMap),
+        /// A request made on behalf of the widget failed
+        Error(IError),
+    }
+
+    #[derive(Debug, PartialEq)]
+    event VpinDialog {
+        UpdateRoles,
+        UpdateSites,
+        UpdateLevels,
+        Error,
+    }
+}
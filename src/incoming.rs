@@ -1,23 +1,24 @@
 //! incoming models the message being sent from the secondary thread
 //! to the application
-use pbgui_vpin::vpin_dialog::LevelMap;
+use crate::custom::CustomMsg;
+#[cfg(feature = "qt")]
+pub use pbgui_vpin::vpin_dialog::LevelMap;
+#[cfg(not(feature = "qt"))]
+/// Sequences of a show, keyed by name, along with their shots
+pub type LevelMap = std::collections::HashMap<String, Vec<String>>;
 
 /// ToIMsg trait should be implemented by the nested incoming message
 /// enums. The trait is used to reduce the visual noise when dealing with IMsg
 ///
 /// # Example
-/// In thread, we send messages to the ui thread using an mpsc::channel .
+/// In thread, we post messages for the ui thread to the Mailbox.
 /// The call would look like this without the trait:
 /// ```ignore
-/// sender
-///   .send(IMsg::VpinDialog(IVPinDialog::Roles(roles)))
-///   .expect("bla bla bla");
+/// mailbox.post(IMsg::VpinDialog(IVPinDialog::Roles(roles)));
 /// ```
 /// With the trait, it can be simplified somewhat to this:
 /// ```ignore
-///  sender
-///    .send(IVpinDialog::Roles(roles).to_imsg())
-///    .expect("unable to send roles");
+/// mailbox.post(IVpinDialog::Roles(roles).to_imsg());
 pub trait ToIMsg {
     fn to_imsg(self) -> IMsg;
 }
@@ -27,17 +28,39 @@ pub enum IMsg {
     PackagesTree(IPackagesTree),
     PackageWiths(IPackageWiths),
     MainToolbar(IMainToolbar),
-    Error(String),
+    Connection(IConnection),
+    Error(IError),
+    Stats(Stats),
+    /// A reply of a family registered by the application
+    Custom(CustomMsg),
 }
 
-pub mod ivpin_dialog;
-pub use ivpin_dialog::IVpinDialog;
+impl IMsg {
+    /// The name of the family the IMsg belongs to, eg "VpinDialog"
+    pub fn family(&self) -> &'static str {
+        match self {
+            IMsg::VpinDialog(_) => "VpinDialog",
+            IMsg::PackagesTree(_) => "PackagesTree",
+            IMsg::PackageWiths(_) => "PackageWiths",
+            IMsg::MainToolbar(_) => "MainToolbar",
+            IMsg::Connection(_) => "Connection",
+            IMsg::Error(_) => "Error",
+            IMsg::Stats(_) => "Stats",
+            IMsg::Custom(msg) => msg.family(),
+        }
+    }
+}
 
-pub mod ipackages_tree;
-pub use ipackages_tree::IPackagesTree;
+pub mod error;
+pub use error::{ErrorCategory, IError};
 
-pub mod ipackage_withs;
-pub use ipackage_withs::IPackageWiths;
+pub mod stats;
+pub use stats::{LatencyHistogram, QueryStats, Stats};
 
-pub mod imain_toolbar;
-pub use imain_toolbar::IMainToolbar;
+pub use crate::family::{
+    connection::{IConnection, ServerInfo},
+    main_toolbar::IMainToolbar,
+    package_withs::IPackageWiths,
+    packages_tree::IPackagesTree,
+    vpin_dialog::IVpinDialog,
+};
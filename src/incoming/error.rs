@@ -0,0 +1,240 @@
+//! The error reported to the application when the secondary thread is unable to
+//! answer a request
+use crate::{
+    backend::{
+        error::{
+            is_connection_error, mentions_connection_error, mentions_statement_timeout, sql_state,
+        },
+        BackendError,
+    },
+    RequestId,
+};
+use std::error::Error;
+use std::fmt;
+
+/// Broadly, what went wrong. The ui may respond differently to each, eg by
+/// asking for credentials on `Permission`, or offering to retry on `Connection`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// The database could not be reached, or the connection was lost
+    Connection,
+    /// The database rejected the query, eg because a show does not exist
+    Query,
+    /// The database answered, but the answer could not be made sense of
+    Parse,
+    /// The connection's role is not allowed to make the query, or failed to
+    /// authenticate
+    Permission,
+    /// The query ran past its statement timeout, and was cancelled
+    Timeout,
+    /// The secondary thread panicked while handling the request. This is a bug.
+    Internal,
+}
+
+impl ErrorCategory {
+    /// Categorize a failure from its message and SQLSTATE code, if any
+    ///
+    /// # Arguments
+    /// * `message` - The error's message
+    /// * `code` - The five character SQLSTATE code reported by postgres
+    pub fn classify(message: &str, code: Option<&str>) -> Self {
+        match code {
+            // query_canceled. the secondary thread answers the cancellations it
+            // makes itself, so those reaching here are the server's own, for
+            // exceeding its statement_timeout
+            Some("57014") => return ErrorCategory::Timeout,
+            Some(code) if code.starts_with("28") || code == "42501" => {
+                return ErrorCategory::Permission
+            }
+            Some(code) if code.starts_with("08") => return ErrorCategory::Connection,
+            Some(_) => return ErrorCategory::Query,
+            None => (),
+        }
+        let lowered = message.to_lowercase();
+        if mentions_statement_timeout(message) {
+            ErrorCategory::Timeout
+        } else if lowered.contains("permission denied") || lowered.contains("authentication failed")
+        {
+            ErrorCategory::Permission
+        } else if mentions_connection_error(message) {
+            ErrorCategory::Connection
+        } else {
+            ErrorCategory::Query
+        }
+    }
+
+    /// The name of the category, eg "permission"
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::Connection => "connection",
+            ErrorCategory::Query => "query",
+            ErrorCategory::Parse => "parse",
+            ErrorCategory::Permission => "permission",
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::Internal => "internal",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Describes a request the secondary thread was unable to answer. Carried by
+/// IMsg::Error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IError {
+    /// The family of the failed request, eg "VpinDialog"
+    pub family: &'static str,
+    /// The id of the failed request, if the failure is attributable to one
+    pub request: Option<RequestId>,
+    pub category: ErrorCategory,
+    /// The SQLSTATE code reported by postgres, eg "42P01", if any
+    pub code: Option<String>,
+    /// Whether the request may succeed if sent again, unchanged
+    pub retryable: bool,
+    pub message: String,
+}
+
+impl IError {
+    /// New up an IError. Whether it is retryable follows from the category:
+    /// connection failures and timeouts are.
+    ///
+    /// # Arguments
+    /// * `family` - The family of the failed request
+    /// * `category` - Broadly, what went wrong
+    /// * `message` - Describes the failure
+    pub fn new<M: Into<String>>(family: &'static str, category: ErrorCategory, message: M) -> Self {
+        Self {
+            family,
+            request: None,
+            category,
+            code: None,
+            retryable: category == ErrorCategory::Connection || category == ErrorCategory::Timeout,
+            message: message.into(),
+        }
+    }
+
+    /// Attribute the failure to the request `request`
+    pub fn with_request(mut self, request: RequestId) -> Self {
+        self.request = Some(request);
+        self
+    }
+
+    /// Describe the failure of a backend query, categorizing it from the error
+    ///
+    /// # Arguments
+    /// * `family` - The family of the failed request
+    /// * `description` - Describes the failed query, eg "Unable to get roles from db"
+    /// * `err` - The error returned by the backend
+    pub(crate) fn from_backend(
+        family: &'static str,
+        description: &str,
+        err: &BackendError,
+    ) -> Self {
+        let message = err.to_string();
+        let code = sql_state(err.as_ref());
+        let category = if is_connection_error(err.as_ref()) {
+            ErrorCategory::Connection
+        } else {
+            ErrorCategory::classify(&message, code.as_deref())
+        };
+        let retryable = match (category, code.as_deref()) {
+            (ErrorCategory::Connection, _) | (ErrorCategory::Timeout, _) => true,
+            // transaction rollbacks, insufficient resources and operator intervention
+            (ErrorCategory::Query, Some(code)) => {
+                code.starts_with("40") || code.starts_with("53") || code.starts_with("57")
+            }
+            _ => false,
+        };
+        Self {
+            family,
+            request: None,
+            category,
+            code,
+            retryable,
+            message: format!("{}: {}", description, message),
+        }
+    }
+}
+
+impl fmt::Display for IError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} error: {}",
+            self.family, self.category, self.message
+        )?;
+        if let Some(code) = &self.code {
+            write!(f, " (SQLSTATE {})", code)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for IError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_classify_by_sql_state() {
+        assert_eq!(
+            ErrorCategory::classify("anything", Some("28P01")),
+            ErrorCategory::Permission
+        );
+        assert_eq!(
+            ErrorCategory::classify("anything", Some("42501")),
+            ErrorCategory::Permission
+        );
+        assert_eq!(
+            ErrorCategory::classify("anything", Some("08006")),
+            ErrorCategory::Connection
+        );
+        assert_eq!(
+            ErrorCategory::classify("connection closed", Some("42P01")),
+            ErrorCategory::Query
+        );
+    }
+
+    #[test]
+    fn can_classify_by_message() {
+        assert_eq!(
+            ErrorCategory::classify("permission denied for table site", None),
+            ErrorCategory::Permission
+        );
+        assert_eq!(
+            ErrorCategory::classify("connection closed", None),
+            ErrorCategory::Connection
+        );
+        assert_eq!(
+            ErrorCategory::classify(
+                "ERROR: canceling statement due to statement timeout",
+                Some("57014")
+            ),
+            ErrorCategory::Timeout
+        );
+        assert_eq!(
+            ErrorCategory::classify("Unknown show: dev03", None),
+            ErrorCategory::Query
+        );
+    }
+
+    #[test]
+    fn backend_errors_are_described() {
+        let err: BackendError = "relation \"site\" does not exist".into();
+        let error = IError::from_backend("PackagesTree", "Unable to get sites from db", &err)
+            .with_request(RequestId::from(7));
+        assert_eq!(error.category, ErrorCategory::Query);
+        assert_eq!(error.request, Some(RequestId::from(7)));
+        assert_eq!(error.code, None);
+        assert!(!error.retryable);
+        assert_eq!(
+            error.to_string(),
+            "PackagesTree query error: Unable to get sites from db: relation \"site\" does not exist"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! Statistics about the queries the secondary thread has made of the database,
+//! by kind. Carried by IMsg::Stats.
+use crate::outgoing::QueryKind;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+// the upper bound of each bucket of the latency histogram, in milliseconds. the
+// last bucket counts the queries slower than the last bound.
+const BOUNDS_MS: [u64; 9] = [1, 5, 10, 25, 50, 100, 250, 1000, 5000];
+
+/// Counts queries by how long they took. Each bucket counts the queries which
+/// took no longer than its upper bound, and longer than the bound of the bucket
+/// before. The last bucket is unbounded.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LatencyHistogram {
+    counts: [u64; BOUNDS_MS.len() + 1],
+}
+
+impl LatencyHistogram {
+    /// Count a query which took `latency`
+    pub fn record(&mut self, latency: Duration) {
+        let bucket = BOUNDS_MS
+            .iter()
+            .position(|bound| latency <= Duration::from_millis(*bound))
+            .unwrap_or(BOUNDS_MS.len());
+        self.counts[bucket] += 1;
+    }
+
+    /// The upper bound of each bucket, along with its count, in ascending order.
+    /// The last bucket's bound is None.
+    pub fn buckets(&self) -> Vec<(Option<Duration>, u64)> {
+        BOUNDS_MS
+            .iter()
+            .map(|bound| Some(Duration::from_millis(*bound)))
+            .chain(std::iter::once(None))
+            .zip(self.counts.iter().cloned())
+            .collect()
+    }
+}
+
+impl fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let buckets = self
+            .buckets()
+            .into_iter()
+            .map(|(bound, count)| match bound {
+                Some(bound) => format!("<={:?}:{}", bound, count),
+                None => format!(">{}ms:{}", BOUNDS_MS[BOUNDS_MS.len() - 1], count),
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", buckets.join(" "))
+    }
+}
+
+/// The statistics of one kind of query
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct QueryStats {
+    /// The number of queries made, including those which failed
+    pub count: u64,
+    /// The number of queries which failed, or were cancelled
+    pub errors: u64,
+    /// The time taken by all of the queries together
+    pub total: Duration,
+    /// The time taken by the slowest query
+    pub max: Duration,
+    pub latency: LatencyHistogram,
+}
+
+impl QueryStats {
+    /// Count a query which took `latency`, and failed unless `ok`
+    pub fn record(&mut self, latency: Duration, ok: bool) {
+        self.count += 1;
+        if !ok {
+            self.errors += 1;
+        }
+        self.total += latency;
+        self.max = std::cmp::max(self.max, latency);
+        self.latency.record(latency);
+    }
+
+    /// The mean time taken by a query, if any have been made
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(self.total / self.count as u32)
+    }
+}
+
+/// The statistics of each kind of query made since the secondary thread started.
+/// Kinds of query which have not been made are absent.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Stats {
+    pub queries: BTreeMap<QueryKind, QueryStats>,
+}
+
+impl Stats {
+    /// New up an empty Stats
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a query of `kind` which took `latency`, and failed unless `ok`
+    pub fn record(&mut self, kind: QueryKind, latency: Duration, ok: bool) {
+        self.queries
+            .entry(kind)
+            .or_insert_with(QueryStats::default)
+            .record(latency, ok);
+    }
+
+    /// The statistics of `kind` of query, if any have been made
+    pub fn get(&self, kind: QueryKind) -> Option<&QueryStats> {
+        self.queries.get(&kind)
+    }
+}
+
+impl fmt::Display for Stats {
+    /// One line per kind of query, eg
+    /// "roles: 3 queries, 0 errors, mean 2ms, max 4ms, latency <=1ms:0 <=5ms:3 ..."
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (kind, stats) in &self.queries {
+            writeln!(
+                f,
+                "{}: {} queries, {} errors, mean {:?}, max {:?}, latency {}",
+                kind,
+                stats.count,
+                stats.errors,
+                stats.mean().unwrap_or_default(),
+                stats.max,
+                stats.latency
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latencies_are_counted_by_bucket() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(Duration::from_micros(500));
+        histogram.record(Duration::from_millis(5));
+        histogram.record(Duration::from_millis(6));
+        histogram.record(Duration::from_secs(60));
+        let buckets = histogram.buckets();
+        assert_eq!(buckets[0], (Some(Duration::from_millis(1)), 1));
+        assert_eq!(buckets[1], (Some(Duration::from_millis(5)), 1));
+        assert_eq!(buckets[2], (Some(Duration::from_millis(10)), 1));
+        assert_eq!(buckets[buckets.len() - 1], (None, 1));
+    }
+
+    #[test]
+    fn queries_are_counted_by_kind() {
+        let mut stats = Stats::new();
+        stats.record(QueryKind::Levels, Duration::from_millis(10), true);
+        stats.record(QueryKind::Levels, Duration::from_millis(30), false);
+        let levels = stats.get(QueryKind::Levels).unwrap();
+        assert_eq!(levels.count, 2);
+        assert_eq!(levels.errors, 1);
+        assert_eq!(levels.max, Duration::from_millis(30));
+        assert_eq!(levels.mean(), Some(Duration::from_millis(20)));
+        assert_eq!(stats.get(QueryKind::Roles), None);
+        assert!(stats
+            .to_string()
+            .starts_with("levels: 2 queries, 1 errors, mean 20ms, max 30ms"));
+    }
+}
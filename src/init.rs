@@ -1,4 +1,16 @@
+pub mod connection;
 pub mod main_toolbar;
 pub mod package_withs;
 pub mod packages_tree;
 pub mod vpin_dialog;
+use crate::Sender;
+use crate::{OMsg, Request, RequestId};
+
+// send `msg` as a new Request, returning its id. Panics with `expect` should
+// the secondary thread have hung up.
+fn send(to_thread_sender: &Sender<Request>, msg: OMsg, expect: &str) -> RequestId {
+    let request = Request::from(msg);
+    let id = request.id;
+    to_thread_sender.send(request).expect(expect);
+    id
+}
@@ -0,0 +1,33 @@
+use super::send;
+use crate::outgoing::OConnection;
+use crate::Sender;
+use crate::{OMsg, Request, RequestId};
+
+/// Request the information needed to populate a connection indicator: the
+/// server details and the connected role. Follow up with periodic
+/// `OConnection::Ping` requests to track latency.
+///
+/// # Arguments
+/// * `to_thread_sender` - A channel Sender used to communicate with the secondary, non ui thread
+///
+/// # Returns
+/// * The ids of the GetServerInfo, GetCurrentUser and Ping requests, in that order
+pub fn init(to_thread_sender: Sender<Request>) -> Vec<RequestId> {
+    vec![
+        send(
+            &to_thread_sender,
+            OMsg::Connection(OConnection::GetServerInfo),
+            "unable to get server info",
+        ),
+        send(
+            &to_thread_sender,
+            OMsg::Connection(OConnection::GetCurrentUser),
+            "unable to get current user",
+        ),
+        send(
+            &to_thread_sender,
+            OMsg::Connection(OConnection::Ping),
+            "unable to ping",
+        ),
+    ]
+}
@@ -1,18 +1,29 @@
-use crate::outgoing::omain_toolbar::OMainToolbar;
-use crate::OMsg;
+use super::send;
+use crate::outgoing::OMainToolbar;
 use crate::Sender;
+use crate::{OMsg, Request, RequestId};
 
-pub fn init(to_thread_sender: Sender<OMsg>) {
-    to_thread_sender
-        .send(OMsg::MainToolbar(OMainToolbar::GetShows))
-        .expect("unable to get shows");
-    to_thread_sender
-        .send(OMsg::MainToolbar(OMainToolbar::GetRoles))
-        .expect("unable to get roles");
-    to_thread_sender
-        .send(OMsg::MainToolbar(OMainToolbar::GetPlatforms))
-        .expect("unable to get platforms");
-    to_thread_sender
-        .send(OMsg::MainToolbar(OMainToolbar::GetSites))
-        .expect("unable to get sites");
+pub fn init(to_thread_sender: Sender<Request>) -> Vec<RequestId> {
+    vec![
+        send(
+            &to_thread_sender,
+            OMsg::MainToolbar(OMainToolbar::GetShows),
+            "unable to get shows",
+        ),
+        send(
+            &to_thread_sender,
+            OMsg::MainToolbar(OMainToolbar::GetRoles),
+            "unable to get roles",
+        ),
+        send(
+            &to_thread_sender,
+            OMsg::MainToolbar(OMainToolbar::GetPlatforms),
+            "unable to get platforms",
+        ),
+        send(
+            &to_thread_sender,
+            OMsg::MainToolbar(OMainToolbar::GetSites),
+            "unable to get sites",
+        ),
+    ]
 }
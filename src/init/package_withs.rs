@@ -1,9 +1,12 @@
-use crate::outgoing::opackage_withs::OPackageWiths;
-use crate::OMsg;
+use super::send;
+use crate::outgoing::OPackageWiths;
 use crate::Sender;
+use crate::{OMsg, Request, RequestId};
 
-pub fn init(to_thread_sender: Sender<OMsg>) {
-    to_thread_sender
-        .send(OMsg::PackageWiths(OPackageWiths::GetPackages))
-        .expect("unable to get packages");
+pub fn init(to_thread_sender: Sender<Request>) -> Vec<RequestId> {
+    vec![send(
+        &to_thread_sender,
+        OMsg::PackageWiths(OPackageWiths::GetPackages),
+        "unable to get packages",
+    )]
 }
@@ -1,12 +1,19 @@
-use crate::outgoing::opackages_tree::OPackagesTree;
-use crate::OMsg;
+use super::send;
+use crate::outgoing::OPackagesTree;
 use crate::Sender;
+use crate::{OMsg, Request, RequestId};
 
-pub fn init(to_thread_sender: Sender<OMsg>) {
-    to_thread_sender
-        .send(OMsg::PackagesTree(OPackagesTree::GetPackages))
-        .expect("unable to get packages");
-    to_thread_sender
-        .send(OMsg::PackagesTree(OPackagesTree::GetSites))
-        .expect("unable to get sites");
+pub fn init(to_thread_sender: Sender<Request>) -> Vec<RequestId> {
+    vec![
+        send(
+            &to_thread_sender,
+            OMsg::PackagesTree(OPackagesTree::GetPackages),
+            "unable to get packages",
+        ),
+        send(
+            &to_thread_sender,
+            OMsg::PackagesTree(OPackagesTree::GetSites),
+            "unable to get sites",
+        ),
+    ]
 }
@@ -1,6 +1,7 @@
-use crate::outgoing::ovpin_dialog::OVpinDialog;
-use crate::OMsg;
+use super::send;
+use crate::outgoing::OVpinDialog;
 use crate::Sender;
+use crate::{OMsg, Request, RequestId};
 
 /// Given a channel Sender and a default show, request that the VpinDialog
 /// be initialized.
@@ -8,19 +9,28 @@ use crate::Sender;
 /// # Arguments
 /// * `to_thread_sender` - A channel Sender used to communicate with the secondary, non ui thread
 /// * `default_show` - The name of the show to gather roles for
-pub fn init<I>(to_thread_sender: Sender<OMsg>, default_show: I)
+///
+/// # Returns
+/// * The ids of the GetRoles, GetSites and GetLevels requests, in that order
+pub fn init<I>(to_thread_sender: Sender<Request>, default_show: I) -> Vec<RequestId>
 where
     I: Into<String>,
 {
-    to_thread_sender
-        .send(OMsg::VpinDialog(OVpinDialog::GetRoles))
-        .expect("unable to get roles");
-    to_thread_sender
-        .send(OMsg::VpinDialog(OVpinDialog::GetSites))
-        .expect("unable to get sites");
-    to_thread_sender
-        .send(OMsg::VpinDialog(OVpinDialog::GetLevels(
-            default_show.into(),
-        )))
-        .expect("unable to get levels");
+    vec![
+        send(
+            &to_thread_sender,
+            OMsg::VpinDialog(OVpinDialog::GetRoles),
+            "unable to get roles",
+        ),
+        send(
+            &to_thread_sender,
+            OMsg::VpinDialog(OVpinDialog::GetSites),
+            "unable to get sites",
+        ),
+        send(
+            &to_thread_sender,
+            OMsg::VpinDialog(OVpinDialog::GetLevels(default_show.into())),
+            "unable to get levels",
+        ),
+    ]
 }
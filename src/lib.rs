@@ -1,13 +1,32 @@
+pub mod custom;
+mod family;
+pub use custom::CustomMsg;
 pub mod incoming;
-pub use incoming::{IMsg, IPackagesTree, IVpinDialog, ToIMsg};
+pub use incoming::{
+    ErrorCategory, IConnection, IError, IMsg, IPackagesTree, IVpinDialog, Stats, ToIMsg,
+};
 pub mod outgoing;
-pub use outgoing::{OMsg, OPackagesTree, OVpinDialog, ToOMsg};
+pub use outgoing::{
+    InstanceId, OConnection, OMsg, OPackagesTree, OVpinDialog, QueryKind, Request, RequestId,
+    ToOMsg,
+};
 pub mod event;
-pub use event::{Event, ToEvent, VpinDialog};
+pub use event::{Event, EventParseError, Signal, ToEvent, VpinDialog};
 pub mod event_handler;
-pub use event_handler::new_event_handler;
+#[cfg(feature = "qt")]
+pub use event_handler::{
+    new_event_handler, new_event_handler_with, EventHandlerBuilder, EventHandlers,
+};
+pub use event_handler::{
+    CurrentSiteSink, ErrorCallbacks, LevelsSink, PackagesSink, PlatformsSink, RolesSink, ShowsSink,
+    SitesSink,
+};
+pub mod backend;
 pub mod client_proxy;
 pub mod init;
+pub mod mailbox;
+pub use mailbox::{Mailbox, MailboxError, Seq};
+pub mod notifier;
 pub mod thread;
 pub use crossbeam_channel::{unbounded as channel, Receiver, Sender};
 
@@ -15,5 +34,6 @@ pub mod prelude {
     pub use super::event::ToEvent;
     pub use super::incoming::ToIMsg;
     pub use super::outgoing::ToOMsg;
+    #[cfg(feature = "qt")]
     pub use qt_thread_conductor::traits::*;
 }
@@ -0,0 +1,356 @@
+//! mailbox pairs each Event signaled by the secondary thread with its IMsg.
+//!
+//! The secondary thread posts each IMsg to the Mailbox, which files it under a
+//! new sequence number. The sequence number travels with the Event, as a Signal,
+//! and the application collects exactly that IMsg. A lost signal or payload
+//! therefore affects only itself, rather than every reply which follows it.
+//!
+//! The Mailbox also tracks the latest request of each kind made on behalf of
+//! each widget instance, so that the application may drop the replies to
+//! requests it has superseded since. See `Mailbox::is_superseded`.
+use crate::{IMsg, InstanceId, Request, RequestId, Signal};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Identifies an IMsg posted to the Mailbox
+pub type Seq = u64;
+
+/// The number of uncollected payloads held before the oldest is discarded
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+struct Slots {
+    next: Seq,
+    payloads: BTreeMap<Seq, Reply>,
+    // the highest sequence number collected thus far
+    last_taken: Option<Seq>,
+    capacity: usize,
+    // the kind of each request being handled, and the widget instance it was
+    // made on behalf of
+    addresses: HashMap<RequestId, Address>,
+    // the latest request of each kind made on behalf of each instance
+    latest: HashMap<Address, RequestId>,
+}
+
+// the kind of a request, along with the widget instance it was made for
+type Address = (&'static str, Option<InstanceId>);
+
+/// Holds the IMsgs posted by the secondary thread until the application
+/// collects them. Clones share the same slots, so one is handed to the
+/// secondary thread and another to the event handler.
+#[derive(Clone)]
+pub struct Mailbox {
+    slots: Arc<Mutex<Slots>>,
+}
+
+impl Default for Mailbox {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl Mailbox {
+    /// New up an empty Mailbox holding up to DEFAULT_CAPACITY payloads
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// New up an empty Mailbox
+    ///
+    /// # Arguments
+    /// * `capacity` - The number of uncollected payloads held before the oldest
+    ///                is discarded
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Arc::new(Mutex::new(Slots {
+                next: 0,
+                payloads: BTreeMap::new(),
+                last_taken: None,
+                capacity,
+                addresses: HashMap::new(),
+                latest: HashMap::new(),
+            })),
+        }
+    }
+
+    fn slots(&self) -> MutexGuard<Slots> {
+        self.slots.lock().expect("Mailbox mutex poisoned")
+    }
+
+    /// File `msg` under the next sequence number. If the Mailbox is full, the
+    /// oldest uncollected payload is discarded.
+    ///
+    /// # Arguments
+    /// * `request` - The id of the Request `msg` answers, if any
+    /// * `msg` - The payload
+    ///
+    /// # Returns
+    /// * The sequence number to signal alongside the Event
+    pub fn post(&self, request: Option<RequestId>, msg: IMsg) -> Seq {
+        let mut slots = self.slots();
+        let seq = slots.next;
+        slots.next += 1;
+        if slots.payloads.len() >= slots.capacity {
+            if let Some(oldest) = slots.payloads.keys().next().cloned() {
+                if let Some(dropped) = slots.payloads.remove(&oldest) {
+                    log::error!(
+                        "Mailbox full. Discarded uncollected {} payload #{}",
+                        dropped.msg.family(),
+                        oldest
+                    );
+                }
+            }
+        }
+        let address = request.and_then(|request| slots.addresses.get(&request).cloned());
+        slots.payloads.insert(
+            seq,
+            Reply {
+                request,
+                kind: address.map(|(kind, _)| kind),
+                instance: address.and_then(|(_, instance)| instance),
+                msg,
+            },
+        );
+        seq
+    }
+
+    /// Echo the kind and instance of `request` in each Reply to it posted from
+    /// now on, until released. The secondary thread addresses each request as
+    /// it handles it.
+    pub(crate) fn address(&self, request: &Request) {
+        self.slots()
+            .addresses
+            .insert(request.id, (request.msg.kind(), request.instance));
+    }
+
+    /// Stop echoing the kind and instance of `request`, having handled it
+    pub(crate) fn release(&self, request: RequestId) {
+        self.slots().addresses.remove(&request);
+    }
+
+    /// Note that `request` has been made, superseding any earlier request of the
+    /// same kind made on behalf of the same instance. Requests which are not
+    /// superseded by later ones, such as control messages, are ignored. The
+    /// secondary thread tracks each request as it arrives.
+    pub(crate) fn track(&self, request: &Request) {
+        if request.msg.is_supersedable() {
+            self.slots()
+                .latest
+                .insert((request.msg.kind(), request.instance), request.id);
+        }
+    }
+
+    /// Whether `reply` answers a request which has since been superseded by a
+    /// later request of the same kind, made on behalf of the same instance, eg
+    /// the levels of a show the user has moved on from. Such replies are stale,
+    /// and may be dropped.
+    pub fn is_superseded(&self, reply: &Reply) -> bool {
+        match (reply.kind, reply.request) {
+            (Some(kind), Some(request)) => self
+                .slots()
+                .latest
+                .get(&(kind, reply.instance))
+                .map_or(false, |latest| *latest > request),
+            _ => false,
+        }
+    }
+
+    /// Take the payload filed under `seq`
+    ///
+    /// # Returns
+    /// * The payload, or MailboxError::Missing if it was never posted, has been
+    ///   collected already, or was discarded
+    pub fn take(&self, seq: Seq) -> Result<Reply, MailboxError> {
+        let mut slots = self.slots();
+        let reply = slots
+            .payloads
+            .remove(&seq)
+            .ok_or(MailboxError::Missing(seq))?;
+        match slots.last_taken {
+            Some(last) if seq < last => {
+                log::warn!("Payload #{} collected out of order, after #{}", seq, last)
+            }
+            _ => slots.last_taken = Some(seq),
+        }
+        Ok(reply)
+    }
+
+    /// Take the payload announced by `signal`, checking that it belongs to the
+    /// same family as the signal's Event. A mismatched payload is discarded.
+    pub fn collect(&self, signal: &Signal) -> Result<Reply, MailboxError> {
+        let reply = self.take(signal.seq)?;
+        if reply.msg.family() != signal.event.family() {
+            return Err(MailboxError::Mismatch {
+                seq: signal.seq,
+                event: signal.event.family().to_string(),
+                payload: reply.msg.family().to_string(),
+            });
+        }
+        Ok(reply)
+    }
+
+    /// The sequence numbers of the payloads waiting to be collected, oldest first
+    pub fn waiting(&self) -> Vec<Seq> {
+        self.slots().payloads.keys().cloned().collect()
+    }
+}
+
+/// A payload posted to the Mailbox
+pub struct Reply {
+    /// The id of the Request the payload answers. None for unsolicited
+    /// payloads, such as changes in the state of the connection.
+    pub request: Option<RequestId>,
+    /// The kind of the Request the payload answers, eg "VpinDialog::GetLevels"
+    pub kind: Option<&'static str>,
+    /// The widget instance the Request was made on behalf of, if any
+    pub instance: Option<InstanceId>,
+    pub msg: IMsg,
+}
+
+/// Reasons a payload could not be collected from the Mailbox
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MailboxError {
+    /// No payload is filed under the sequence number
+    Missing(Seq),
+    /// The payload does not belong to the family of the Event signaled with it
+    Mismatch {
+        seq: Seq,
+        event: String,
+        payload: String,
+    },
+}
+
+impl fmt::Display for MailboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MailboxError::Missing(seq) => write!(f, "No payload #{} in mailbox", seq),
+            MailboxError::Mismatch {
+                seq,
+                event,
+                payload,
+            } => write!(
+                f,
+                "Payload #{} is a {} message, but was signaled as a {} event",
+                seq, payload, event
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MailboxError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        event::PackagesTree, ErrorCategory, IError, IPackagesTree, OMsg, OPackagesTree,
+        OVpinDialog, ToEvent, ToIMsg, ToOMsg,
+    };
+
+    fn sites(sites: &[&str]) -> IMsg {
+        IPackagesTree::Sites {
+            sites: sites.iter().map(|s| s.to_string()).collect(),
+            current: "any".to_string(),
+        }
+        .to_imsg()
+    }
+
+    fn oops() -> IMsg {
+        IMsg::Error(IError::new("PackagesTree", ErrorCategory::Query, "oops"))
+    }
+
+    #[test]
+    fn payloads_are_collected_by_sequence_number() {
+        let mailbox = Mailbox::new();
+        let first = mailbox.post(None, sites(&["portland"]));
+        let second = mailbox.post(None, oops());
+        assert_ne!(first, second);
+        // collecting out of order does not mis-route either payload
+        assert!(matches!(
+            mailbox.take(second).map(|r| r.msg),
+            Ok(IMsg::Error(_))
+        ));
+        match mailbox.take(first).map(|r| r.msg) {
+            Ok(IMsg::PackagesTree(IPackagesTree::Sites { sites, .. })) => {
+                assert_eq!(sites, vec!["portland"])
+            }
+            _ => panic!("expected sites"),
+        }
+        assert!(mailbox.waiting().is_empty());
+    }
+
+    #[test]
+    fn missing_payloads_are_reported() {
+        let mailbox = Mailbox::new();
+        let seq = mailbox.post(None, sites(&[]));
+        assert!(mailbox.take(seq).is_ok());
+        assert_eq!(mailbox.take(seq).unwrap_err(), MailboxError::Missing(seq));
+        assert_eq!(mailbox.take(99).unwrap_err(), MailboxError::Missing(99));
+    }
+
+    #[test]
+    fn mismatched_payloads_are_reported() {
+        let mailbox = Mailbox::new();
+        let seq = mailbox.post(None, oops());
+        let signal = Signal::new(seq, None, PackagesTree::GetSites.to_event());
+        assert_eq!(
+            mailbox.collect(&signal).unwrap_err(),
+            MailboxError::Mismatch {
+                seq,
+                event: "PackagesTree".to_string(),
+                payload: "Error".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn replies_echo_the_instance_of_their_request() {
+        let mailbox = Mailbox::new();
+        let request = OPackagesTree::GetSites.to_request_for(InstanceId::from(2));
+        mailbox.address(&request);
+        let addressed = mailbox.post(Some(request.id), sites(&[]));
+        mailbox.release(request.id);
+        let released = mailbox.post(Some(request.id), sites(&[]));
+        let addressed = mailbox.take(addressed).unwrap();
+        assert_eq!(addressed.instance, Some(InstanceId::from(2)));
+        assert_eq!(addressed.kind, Some("PackagesTree::GetSites"));
+        assert_eq!(mailbox.take(released).unwrap().instance, None);
+    }
+
+    #[test]
+    fn replies_to_superseded_requests_are_recognized() {
+        let mailbox = Mailbox::new();
+        let answer = |request: &Request| {
+            mailbox.address(request);
+            let seq = mailbox.post(Some(request.id), sites(&[]));
+            mailbox.release(request.id);
+            mailbox.take(seq).unwrap()
+        };
+        let first = OVpinDialog::GetLevels("dev01".to_string()).to_request();
+        let second = OVpinDialog::GetLevels("dev02".to_string()).to_request();
+        let other = OVpinDialog::GetLevels("dev03".to_string()).to_request_for(InstanceId::from(1));
+        let quit = Request::from(OMsg::Quit);
+        for request in &[&first, &second, &other, &quit] {
+            mailbox.track(request);
+        }
+        assert!(mailbox.is_superseded(&answer(&first)));
+        assert!(!mailbox.is_superseded(&answer(&second)));
+        // requests for other instances, and unsolicited payloads, are not
+        assert!(!mailbox.is_superseded(&answer(&other)));
+        let seq = mailbox.post(None, sites(&[]));
+        assert!(!mailbox.is_superseded(&mailbox.take(seq).unwrap()));
+    }
+
+    #[test]
+    fn oldest_payload_is_discarded_when_full() {
+        let mailbox = Mailbox::with_capacity(2);
+        let first = mailbox.post(None, sites(&[]));
+        let second = mailbox.post(None, sites(&[]));
+        let third = mailbox.post(None, sites(&[]));
+        assert_eq!(mailbox.waiting(), vec![second, third]);
+        assert_eq!(
+            mailbox.take(first).unwrap_err(),
+            MailboxError::Missing(first)
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! Notifier abstracts how the secondary thread tells the application that an
+//! IMsg is waiting for it in the Mailbox. In a QT application this is the
+//! Conductor, which raises a signal on the ui thread. Elsewhere, the Signals may
+//! simply be sent down a channel.
+use crate::Signal;
+use crossbeam_channel::Sender;
+#[cfg(feature = "qt")]
+use qt_thread_conductor::conductor::Conductor;
+
+/// Signal the application that the state described by the signal's Event has
+/// changed. The details are posted to the Mailbox, as an IMsg, under the
+/// signal's sequence number.
+pub trait Notifier {
+    fn signal(&mut self, signal: Signal);
+}
+
+/// Lets a borrowed Notifier, including a `&mut dyn Notifier`, be passed on to
+/// functions taking `impl Notifier`
+impl<N: Notifier + ?Sized> Notifier for &mut N {
+    fn signal(&mut self, signal: Signal) {
+        (**self).signal(signal)
+    }
+}
+
+#[cfg(feature = "qt")]
+impl Notifier for Conductor<Signal> {
+    fn signal(&mut self, signal: Signal) {
+        Conductor::signal(self, signal)
+    }
+}
+
+/// A Notifier which sends each Signal down a channel, for use without QT.
+pub struct ChannelNotifier {
+    sender: Sender<Signal>,
+}
+
+impl ChannelNotifier {
+    /// New up a ChannelNotifier
+    ///
+    /// # Arguments
+    /// * `sender` - The Sender the Signals are sent with
+    pub fn new(sender: Sender<Signal>) -> Self {
+        Self { sender }
+    }
+}
+
+impl Notifier for ChannelNotifier {
+    fn signal(&mut self, signal: Signal) {
+        if let Err(err) = self.sender.send(signal) {
+            log::error!("Unable to send signal {}: receiver hung up", err.0);
+        }
+    }
+}
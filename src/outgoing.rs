@@ -1,22 +1,287 @@
 //! models message being sent from the application to the secondary thread
-pub mod ovpin_dialog;
-pub use ovpin_dialog::OVpinDialog;
-pub mod opackages_tree;
-pub use opackages_tree::OPackagesTree;
-pub mod opackage_withs;
-pub use opackage_withs::OPackageWiths;
-pub mod omain_toolbar;
-pub use omain_toolbar::OMainToolbar;
+use crate::custom::CustomMsg;
+pub use crate::family::{
+    connection::OConnection, main_toolbar::OMainToolbar, package_withs::OPackageWiths,
+    packages_tree::OPackagesTree, vpin_dialog::OVpinDialog,
+};
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 ///
 pub trait ToOMsg {
     fn to_omsg(self) -> OMsg;
+
+    /// Wrap the message in a Request, under a new RequestId
+    fn to_request(self) -> Request
+    where
+        Self: Sized,
+    {
+        Request::new(self.to_omsg())
+    }
+
+    /// Wrap the message in a Request made on behalf of the widget `instance`,
+    /// under a new RequestId
+    fn to_request_for(self, instance: InstanceId) -> Request
+    where
+        Self: Sized,
+    {
+        self.to_request().for_instance(instance)
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum OMsg {
     VpinDialog(OVpinDialog),
     PackagesTree(OPackagesTree),
     PackageWiths(OPackageWiths),
     MainToolbar(OMainToolbar),
+    Connection(OConnection),
+    /// A request of a family registered by the application
+    Custom(CustomMsg),
+    /// Cancel the request with the given id. A queued request is dropped, and a
+    /// running query is aborted.
+    Cancel(RequestId),
+    /// Discard the cached results of the given kind of query, so that the next
+    /// request making it goes to the database
+    InvalidateCache(QueryKind),
+    /// Ask for the statistics of the queries made so far, answered with IMsg::Stats
+    GetStats,
     Quit,
 }
+
+impl OMsg {
+    /// The name of the family the OMsg belongs to, eg "VpinDialog". Control
+    /// messages are named after themselves.
+    pub fn family(&self) -> &'static str {
+        match self {
+            OMsg::VpinDialog(_) => OVpinDialog::FAMILY,
+            OMsg::PackagesTree(_) => OPackagesTree::FAMILY,
+            OMsg::PackageWiths(_) => OPackageWiths::FAMILY,
+            OMsg::MainToolbar(_) => OMainToolbar::FAMILY,
+            OMsg::Connection(_) => OConnection::FAMILY,
+            OMsg::Custom(msg) => msg.family(),
+            OMsg::Cancel(_) => "Cancel",
+            OMsg::InvalidateCache(_) => "InvalidateCache",
+            OMsg::GetStats => "GetStats",
+            OMsg::Quit => "Quit",
+        }
+    }
+
+    /// Names the kind of request, eg "VpinDialog::GetLevels", regardless of
+    /// its arguments
+    pub fn kind(&self) -> &'static str {
+        match self {
+            OMsg::VpinDialog(msg) => msg.kind(),
+            OMsg::PackagesTree(msg) => msg.kind(),
+            OMsg::PackageWiths(msg) => msg.kind(),
+            OMsg::MainToolbar(msg) => msg.kind(),
+            OMsg::Connection(msg) => msg.kind(),
+            OMsg::Custom(msg) => msg.name(),
+            OMsg::Cancel(_) => "Cancel",
+            OMsg::InvalidateCache(_) => "InvalidateCache",
+            OMsg::GetStats => "GetStats",
+            OMsg::Quit => "Quit",
+        }
+    }
+
+    /// Whether a queued request is superseded by a later request of the same kind,
+    /// made on behalf of the same widget instance. Queries are, as only the latest
+    /// answer is of interest. Control messages are not, nor are custom requests,
+    /// as they may write to the database.
+    pub fn is_supersedable(&self) -> bool {
+        match self {
+            OMsg::Custom(_)
+            | OMsg::Cancel(_)
+            | OMsg::InvalidateCache(_)
+            | OMsg::GetStats
+            | OMsg::Quit => false,
+            _ => true,
+        }
+    }
+
+    /// The kinds of query whose cached results are stale once the secondary
+    /// thread has handled the message. Custom messages which write to the
+    /// database declare the kinds they affect with `CustomMsg::invalidating`.
+    /// Handlers may also invalidate kinds as they go, with `Context::invalidate`.
+    pub fn invalidates(&self) -> Vec<QueryKind> {
+        match self {
+            OMsg::InvalidateCache(kind) => vec![*kind],
+            OMsg::Custom(msg) => msg.invalidates().to_vec(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// The kinds of query the secondary thread makes of the database. Results are
+/// cached, and invalidated, by kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum QueryKind {
+    Roles,
+    Sites,
+    Platforms,
+    Packages,
+    Shows,
+    Levels,
+}
+
+impl QueryKind {
+    /// The name of the kind of query, eg "roles"
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryKind::Roles => "roles",
+            QueryKind::Sites => "sites",
+            QueryKind::Platforms => "platforms",
+            QueryKind::Packages => "packages",
+            QueryKind::Shows => "shows",
+            QueryKind::Levels => "levels",
+        }
+    }
+}
+
+impl fmt::Display for QueryKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Identifies a Request. The secondary thread echoes the id in each Signal and
+/// Reply it sends in response, so the application can tell which request a
+/// reply answers, and drop replies to requests it has since superseded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RequestId(u64);
+
+impl RequestId {
+    /// Allocate a RequestId which is unique within the process. Ids increase
+    /// monotonically, so a later request has a greater id.
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        RequestId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// The id as a number
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
+impl From<u64> for RequestId {
+    fn from(value: u64) -> Self {
+        RequestId(value)
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for RequestId {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(RequestId)
+    }
+}
+
+/// Identifies one of several instances of the same kind of widget, eg one of two
+/// VpinDialogs. A Request made on behalf of an instance carries its id, which the
+/// secondary thread echoes in each Reply, so that the reply reaches the same
+/// instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InstanceId(u32);
+
+impl InstanceId {
+    /// The id as a number
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+}
+
+impl From<u32> for InstanceId {
+    fn from(value: u32) -> Self {
+        InstanceId(value)
+    }
+}
+
+impl fmt::Display for InstanceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// An OMsg, along with the id identifying it. This is what the application
+/// sends to the secondary thread.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Request {
+    pub id: RequestId,
+    pub msg: OMsg,
+    /// The widget instance the request is made on behalf of, if there are
+    /// several of its kind
+    pub instance: Option<InstanceId>,
+}
+
+impl Request {
+    /// New up a Request for `msg`, under a new RequestId
+    pub fn new(msg: OMsg) -> Self {
+        Self {
+            id: RequestId::next(),
+            msg,
+            instance: None,
+        }
+    }
+
+    /// Make the request on behalf of the widget `instance`
+    pub fn for_instance(mut self, instance: InstanceId) -> Self {
+        self.instance = Some(instance);
+        self
+    }
+}
+
+impl From<OMsg> for Request {
+    fn from(msg: OMsg) -> Self {
+        Request::new(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_given_increasing_ids() {
+        let first = OVpinDialog::GetRoles.to_request();
+        let second = Request::from(OMsg::Quit);
+        assert!(second.id > first.id);
+        assert_eq!(first.msg, OMsg::VpinDialog(OVpinDialog::GetRoles));
+    }
+
+    #[test]
+    fn invalidate_cache_invalidates_its_kind() {
+        assert_eq!(
+            OMsg::InvalidateCache(QueryKind::Sites).invalidates(),
+            vec![QueryKind::Sites]
+        );
+        assert!(OMsg::VpinDialog(OVpinDialog::GetSites)
+            .invalidates()
+            .is_empty());
+        assert!(!OMsg::InvalidateCache(QueryKind::Sites).is_supersedable());
+    }
+
+    #[test]
+    fn requests_may_be_made_for_an_instance() {
+        let request = OVpinDialog::GetRoles.to_request();
+        assert_eq!(request.instance, None);
+        let request = OVpinDialog::GetRoles.to_request_for(InstanceId::from(2));
+        assert_eq!(request.instance, Some(InstanceId::from(2)));
+    }
+
+    #[test]
+    fn can_round_trip_request_id() {
+        let id = RequestId::from(42);
+        assert_eq!(id.to_string(), "42");
+        assert_eq!("42".parse(), Ok(id));
+    }
+}
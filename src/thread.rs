@@ -1,20 +1,62 @@
 //! handle queries in a separate thread
 use crate::{
-    client_proxy::{ClientProxy, ConnectParams},
-    event::{MainToolbar, PackageWiths, PackagesTree},
-    incoming::{IMainToolbar, IPackageWiths, IPackagesTree},
-    outgoing::{OMainToolbar, OPackageWiths, OPackagesTree},
-    Event, IMsg, IVpinDialog, OMsg, OVpinDialog, ToEvent, ToIMsg, VpinDialog,
+    backend::{
+        error::{is_cancellation, is_connection_error},
+        BackendError, Connector, PackratBackend,
+    },
+    event::{Connection, MainToolbar, PackageWiths, PackagesTree},
+    incoming::{IMainToolbar, IPackageWiths, IPackagesTree, LevelMap},
+    notifier::Notifier,
+    outgoing::{OConnection, OMainToolbar, OPackageWiths, OPackagesTree},
+    ErrorCategory, Event, IConnection, IError, IMsg, IVpinDialog, Mailbox, OMsg, OVpinDialog,
+    QueryKind, Request, RequestId, Signal, Stats, ToEvent, ToIMsg, VpinDialog,
 };
 use crossbeam_channel::{Receiver, Sender};
-use crossbeam_utils::thread;
 use log;
-use packybara::packrat::PackratDb;
-use packybara::traits::*;
-use pbgui_vpin::vpin_dialog::LevelMap;
+use postgres::error::SqlState;
+#[cfg(feature = "qt")]
 use qt_core::Slot;
+#[cfg(feature = "qt")]
 use qt_thread_conductor::conductor::Conductor;
+#[cfg(feature = "qt")]
 use qt_widgets::{cpp_core::MutPtr, QApplication, QMainWindow};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+pub mod connection;
+pub use connection::ReconnectPolicy;
+use connection::{match_connection, DbConnection};
+
+mod intake;
+use intake::{spawn_intake, supersede, InFlight, Stop};
+
+pub mod shutdown;
+#[cfg(feature = "qt")]
+use shutdown::join_worker;
+pub use shutdown::{shutdown_worker, ShutdownError, ShutdownPolicy};
+
+mod shared;
+use shared::{gather, Query, Results, SharedQueries};
+
+pub mod cache;
+use cache::Cache;
+pub use cache::CachePolicy;
+
+pub mod timeout;
+use timeout::spawn_watchdog;
+pub use timeout::TimeoutPolicy;
+
+pub mod site;
+pub use site::SitePolicy;
+
+pub mod registry;
+use registry::Context;
+pub use registry::Handlers;
 
 pub mod vpin_dialog;
 use vpin_dialog::match_vpin_dialog;
@@ -29,76 +71,742 @@ pub mod main_toolbar;
 use main_toolbar::match_main_toolbar;
 
 /// Create the thread that handles requests for data from the ui. The thread
-/// receives messages via the `receiver`, matches against them, and sends data
-/// back to the UI via the `sender`. Finally, triggering an appropriate update
-/// via the `conductor`. The `conductor` and `sender` work as a team. The `sender`
-/// handles complex data, and the `conductor` notifies QT.
+/// receives messages via the `receiver`, matches against them, and posts data
+/// for the UI to the `mailbox`. Finally, triggering an appropriate update
+/// via the `conductor`. The `conductor` and `mailbox` work as a team. The `mailbox`
+/// holds complex data, and the `conductor` notifies QT, signaling the sequence
+/// number the data was posted under.
+///
+/// The thread connects to the database itself, reporting its progress via
+/// `Event::Connection`. If the connection cannot be established, or is lost
+/// later on, it reconnects according to the default `ReconnectPolicy`, queueing
+/// requests in the meantime.
+///
+/// A queued request is dropped if a later request of the same kind, made on behalf
+/// of the same widget instance, arrives before it is handled, eg GetLevels for one
+/// show followed by GetLevels for another. A request which is already running when
+/// superseded is answered, but the event handler drops its reply. See
+/// `Mailbox::is_superseded`.
+/// Sending OMsg::Cancel with the id of a request drops it if queued, or aborts its
+/// query if running. Either way, the request goes unanswered.
+///
+/// Queued requests which make the same query, eg GetSites from the vpin dialog,
+/// the packages tree and the main toolbar, are answered by running the query once.
+/// The roles, sites and platforms are cached for a while, per the default
+/// `CachePolicy`, and may be refreshed by sending OMsg::InvalidateCache. Requests
+/// which write to the database invalidate the results they make stale once
+/// handled. See `CustomMsg::invalidating` and `Context::invalidate`.
+///
+/// The count, error count and latency of each kind of query are recorded, and
+/// sent in reply to OMsg::GetStats as IMsg::Stats. They may also be written to
+/// `WorkerConfig::stats_file` when the thread stops.
+///
+/// Queries may be given a timeout, per `TimeoutPolicy`. A query which runs past
+/// its timeout is cancelled, and answered with an `ErrorCategory::Timeout` error.
+///
+/// When the application quits, OMsg::Quit is acted upon at once. By default, the
+/// running query is cancelled and the queued requests are dropped. See
+/// `ShutdownPolicy`. Should the thread not stop within `WorkerConfig::join_timeout`,
+/// it is left running, and `ShutdownError::TimedOut` is returned rather than hang.
+/// The application may then exit the process.
+///
+/// Each request is handed to the handler registered for its family. See
+/// `create_with_handlers` to register handlers of the application's own.
+///
+/// A panic while handling a request is answered with an `ErrorCategory::Internal`
+/// error. Should the thread panic otherwise, it is restarted with a fresh
+/// connection, up to `WorkerConfig::max_restarts` times.
+///
+/// See `spawn_worker` to run the same thread without QT.
 ///
 /// # Arguments
+/// * `connector` - Opens the backend queried by the thread. Typically ConnectParams.
 /// * `main_window` - Mutable MutPtr wrapped QMainWindow instance
-/// * `conductor` - Mutable instance of the Conductor<Event>, responsible for signaling
+/// * `conductor` - Mutable instance of the Conductor<Signal>, responsible for signaling
 ///                 to QT
-/// * sender - Sends IMsg's to the UI thread
-/// * receiver - Receives OMsg's from the UI thread
+/// * mailbox - Holds IMsg's for the UI thread
+/// * receiver - Receives Requests from the UI thread
 ///
 /// # Returns
-/// * i32 - The status
-pub fn create(
-    connect_params: ConnectParams,
+/// * Ok(i32) - The status returned by QApplication::exec, once the thread has stopped
+/// * Err(ShutdownError) - Should the thread not stop in time, or panic
+#[cfg(feature = "qt")]
+pub fn create<C>(
+    connector: C,
+    main_window: MutPtr<QMainWindow>,
+    conductor: Conductor<Signal>,
+    mailbox: Mailbox,
+    receiver: Receiver<Request>,
+) -> Result<i32, ShutdownError>
+where
+    C: Connector + Send + 'static,
+{
+    create_with(
+        connector,
+        WorkerConfig::default(),
+        main_window,
+        conductor,
+        mailbox,
+        receiver,
+    )
+}
+
+/// As `create`, configuring the thread with `config` rather than the defaults
+#[cfg(feature = "qt")]
+pub fn create_with<C>(
+    connector: C,
+    config: WorkerConfig,
+    main_window: MutPtr<QMainWindow>,
+    conductor: Conductor<Signal>,
+    mailbox: Mailbox,
+    receiver: Receiver<Request>,
+) -> Result<i32, ShutdownError>
+where
+    C: Connector + Send + 'static,
+{
+    create_with_handlers(
+        connector,
+        config,
+        Handlers::new(),
+        main_window,
+        conductor,
+        mailbox,
+        receiver,
+    )
+}
+
+/// As `create_with`, handing requests to `handlers`, eg the built-in families
+/// along with the application's own
+#[cfg(feature = "qt")]
+pub fn create_with_handlers<C>(
+    connector: C,
+    config: WorkerConfig,
+    handlers: Handlers<C>,
     mut main_window: MutPtr<QMainWindow>,
-    mut conductor: Conductor<Event>,
-    sender: Sender<IMsg>,
-    receiver: Receiver<OMsg>,
-) -> i32 {
-    let mut result = 0;
-    thread::scope(|s| {
-        let handle = s.spawn(|_| {
-            let client = match ClientProxy::connect(connect_params) {
-                Ok(client) => client,
-                Err(err) => {
-                    sender
-                        .send(IMsg::Error(err.to_string()))
-                        .expect("unable to send roles");
-                    conductor.signal(Event::Error);
-                    panic!("unable to connect to database");
+    conductor: Conductor<Signal>,
+    mailbox: Mailbox,
+    receiver: Receiver<Request>,
+) -> Result<i32, ShutdownError>
+where
+    C: Connector + Send + 'static,
+{
+    let join_timeout = config.join_timeout;
+    let handle =
+        spawn_worker_with_handlers(connector, config, handlers, conductor, mailbox, receiver);
+    // the application needs to show and execute before the thread is joined
+    let result = unsafe {
+        main_window.show();
+        QApplication::exec()
+    };
+    // the quit slot has told the thread to quit by now
+    join_worker(handle, join_timeout).map_err(|err| {
+        log::error!("{}", err);
+        err
+    })?;
+    Ok(result)
+}
+
+/// Spawn the thread that handles requests for data, without depending upon the
+/// QT event loop. This is the headless counterpart to `create`, suitable for
+/// command line tools, daemons and tests. The thread runs until it receives
+/// OMsg::Quit, or every Sender<Request> has been dropped.
+///
+/// # Arguments
+/// * `connector` - Opens the backend queried by the thread. Typically ConnectParams.
+/// * `notifier` - Signals each Event, eg a ChannelNotifier
+/// * mailbox - Holds IMsg's for the application
+/// * receiver - Receives Requests from the application
+///
+/// # Returns
+/// * The JoinHandle of the spawned thread
+pub fn spawn_worker<C, N>(
+    connector: C,
+    notifier: N,
+    mailbox: Mailbox,
+    receiver: Receiver<Request>,
+) -> JoinHandle<()>
+where
+    C: Connector + Send + 'static,
+    N: Notifier + Send + 'static,
+{
+    spawn_worker_with(
+        connector,
+        WorkerConfig::default(),
+        notifier,
+        mailbox,
+        receiver,
+    )
+}
+
+/// As `spawn_worker`, configuring the thread with `config` rather than the defaults
+pub fn spawn_worker_with<C, N>(
+    connector: C,
+    config: WorkerConfig,
+    notifier: N,
+    mailbox: Mailbox,
+    receiver: Receiver<Request>,
+) -> JoinHandle<()>
+where
+    C: Connector + Send + 'static,
+    N: Notifier + Send + 'static,
+{
+    spawn_worker_with_handlers(
+        connector,
+        config,
+        Handlers::new(),
+        notifier,
+        mailbox,
+        receiver,
+    )
+}
+
+/// As `spawn_worker_with`, handing requests to `handlers`, eg the built-in
+/// families along with the application's own
+pub fn spawn_worker_with_handlers<C, N>(
+    connector: C,
+    config: WorkerConfig,
+    handlers: Handlers<C>,
+    mut notifier: N,
+    mailbox: Mailbox,
+    receiver: Receiver<Request>,
+) -> JoinHandle<()>
+where
+    C: Connector + Send + 'static,
+    N: Notifier + Send + 'static,
+{
+    std::thread::spawn(move || {
+        supervise(
+            connector,
+            config,
+            handlers,
+            &mut notifier,
+            &mailbox,
+            &receiver,
+        )
+    })
+}
+
+/// Configures the secondary thread
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkerConfig {
+    /// How to reconnect when the connection to the database is lost. Also
+    /// governs the delay before restarting after a panic.
+    pub reconnect: ReconnectPolicy,
+    /// Which query results to cache, and for how long
+    pub cache: CachePolicy,
+    /// How long each kind of query may run before it is cancelled, and
+    /// answered with an ErrorCategory::Timeout error
+    pub timeouts: TimeoutPolicy,
+    /// How many times the secondary thread is restarted after panicking,
+    /// before it gives up
+    pub max_restarts: u32,
+    /// What to do with outstanding requests when told to quit
+    pub shutdown: ShutdownPolicy,
+    /// How long `create` waits for the thread to stop once the application
+    /// has quit
+    pub join_timeout: Duration,
+    /// Where to write the query statistics when the thread stops, if anywhere
+    pub stats_file: Option<PathBuf>,
+    /// How to work out the site the application is running at, including the
+    /// site to fall back to. See `SitePolicy::with_default`.
+    pub site: SitePolicy,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self {
+            reconnect: ReconnectPolicy::default(),
+            cache: CachePolicy::default(),
+            timeouts: TimeoutPolicy::default(),
+            max_restarts: 5,
+            shutdown: ShutdownPolicy::default(),
+            join_timeout: Duration::from_secs(5),
+            stats_file: None,
+            site: SitePolicy::default(),
+        }
+    }
+}
+
+/// Run the secondary thread until told to quit, restarting it with a fresh
+/// connection should it panic outside of a request. This is the body of the
+/// secondary thread.
+fn supervise<C, N>(
+    connector: C,
+    config: WorkerConfig,
+    handlers: Handlers<C>,
+    conductor: &mut N,
+    mailbox: &Mailbox,
+    receiver: &Receiver<Request>,
+) where
+    C: Connector + 'static,
+    N: Notifier,
+{
+    let max_restarts = config.max_restarts;
+    let policy = config.reconnect.clone();
+    let stats_file = config.stats_file.clone();
+    let mut worker = Worker::new(connector, config, handlers, receiver, mailbox);
+    let mut restarts = 0;
+    loop {
+        let panic = match catch_unwind(AssertUnwindSafe(|| worker.run(conductor, mailbox))) {
+            Ok(()) => break,
+            Err(panic) => panic_message(&*panic),
+        };
+        restarts += 1;
+        if restarts > max_restarts {
+            log::error!(
+                "From secondary thread. Giving up after {} restarts: {}",
+                max_restarts,
+                panic
+            );
+            let message = format!("The secondary thread panicked and has stopped: {}", panic);
+            report(
+                IError::new(WORKER_FAMILY, ErrorCategory::Internal, message),
+                conductor,
+                mailbox,
+            );
+            break;
+        }
+        let delay = policy.delay(restarts);
+        log::error!(
+            "From secondary thread. Restarting in {:?} after panicking: {}",
+            delay,
+            panic
+        );
+        let message = format!("The secondary thread panicked and will restart: {}", panic);
+        report(
+            IError::new(WORKER_FAMILY, ErrorCategory::Internal, message),
+            conductor,
+            mailbox,
+        );
+        worker.connection.reset();
+        std::thread::sleep(delay);
+    }
+    if let Some(path) = stats_file {
+        dump_stats(&worker.stats, &path);
+    }
+}
+
+// write the query statistics to the file at `path`, logging rather than failing
+// should it not be writable, as the thread is stopping anyway
+fn dump_stats(stats: &Stats, path: &Path) {
+    match std::fs::write(path, stats.to_string()) {
+        Ok(()) => log::info!("Wrote query statistics to {}", path.display()),
+        Err(err) => log::error!(
+            "Unable to write query statistics to {}: {}",
+            path.display(),
+            err
+        ),
+    }
+}
+
+// errors concerning the secondary thread as a whole are attributed to this family
+const WORKER_FAMILY: &str = "Worker";
+
+// the message a panic was raised with, if any
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown cause".to_string()
+    }
+}
+
+/// The state of the secondary thread. It is kept apart from the loop handling
+/// requests, so that the loop may be restarted without losing queued requests.
+struct Worker<C: Connector> {
+    connection: DbConnection<C>,
+    // the handler of each family
+    handlers: Handlers<C>,
+    cache: Cache,
+    // the statistics of the queries made since the thread started
+    stats: Stats,
+    // the site the application is running at, as sent with the sites
+    current_site: String,
+    // the kinds of query invalidated by the handler of the current request
+    invalidated: Vec<QueryKind>,
+    // tracks the request being handled, so that the intake may cancel it
+    in_flight: Arc<InFlight<C::Cancel>>,
+    timeouts: TimeoutPolicy,
+    // sends the deadline of each request to the watchdog
+    deadlines: Sender<(RequestId, Instant)>,
+    // receives requests forwarded by the intake
+    receiver: Receiver<Request>,
+    // requests waiting to be handled
+    pending: VecDeque<Request>,
+}
+
+impl<C: Connector + 'static> Worker<C> {
+    fn new(
+        connector: C,
+        config: WorkerConfig,
+        handlers: Handlers<C>,
+        receiver: &Receiver<Request>,
+        mailbox: &Mailbox,
+    ) -> Self {
+        // the intake forwards requests, acting upon cancellations itself
+        let in_flight = Arc::new(InFlight::new());
+        let (intake_sender, intake_receiver) = crossbeam_channel::unbounded();
+        spawn_intake(
+            receiver.clone(),
+            intake_sender,
+            in_flight.clone(),
+            config.shutdown,
+            mailbox.clone(),
+        );
+        // the watchdog cancels requests which run past their timeout
+        let (deadlines, deadlines_receiver) = crossbeam_channel::unbounded();
+        spawn_watchdog(in_flight.clone(), deadlines_receiver);
+        Self {
+            connection: DbConnection::new(connector, config.reconnect, config.shutdown),
+            handlers,
+            cache: Cache::new(config.cache),
+            stats: Stats::new(),
+            current_site: config.site.resolve(),
+            invalidated: Vec::new(),
+            in_flight,
+            timeouts: config.timeouts,
+            deadlines,
+            receiver: intake_receiver,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Handle requests until told to quit
+    fn run<N: Notifier>(&mut self, conductor: &mut N, mailbox: &Mailbox) {
+        loop {
+            if self.in_flight.is_abandoned() {
+                self.abandon();
+                return;
+            }
+            if !self.connection.is_connected()
+                && !self
+                    .connection
+                    .connect(&self.receiver, &mut self.pending, conductor, mailbox)
+            {
+                log::info!("From secondary thread. Quitting while connecting to database");
+                return;
+            }
+            // gather any requests which have arrived in the meantime, so that those
+            // which have been superseded are dropped rather than handled
+            self.pending.extend(self.receiver.try_iter());
+            supersede(&mut self.pending);
+            let request = match self.pending.pop_front() {
+                Some(request) => request,
+                None => {
+                    // nothing is queued, so any cancellation yet to be acted upon
+                    // is of a request which has finished, or was never made
+                    self.in_flight.forget_cancelled(|| self.receiver.is_empty());
+                    match self.receiver.recv() {
+                        Ok(request) => request,
+                        Err(_) => {
+                            log::info!(
+                                "From secondary thread. Quitting as the application hung up"
+                            );
+                            return;
+                        }
+                    }
                 }
             };
-            let mut db = PackratDb::new(client);
-            //let mut show: Option<String> = None;
-            loop {
-                let msg = receiver.recv().expect("Unable to unwrap received msg");
-                match msg {
-                    OMsg::VpinDialog(msg) => {
-                        match_vpin_dialog(msg, &mut db, &mut conductor, &sender);
-                    }
-                    OMsg::PackagesTree(msg) => {
-                        match_packages_tree(msg, &mut db, &mut conductor, &sender);
+            // requests queued alongside this one which make the same query share
+            // its result
+            let mut batch = gather(request, &mut self.pending).into_iter();
+            let mut results = Results::new();
+            while let Some(request) = batch.next() {
+                if self.in_flight.is_abandoned() {
+                    self.pending.push_front(request);
+                    self.pending.extend(batch);
+                    self.abandon();
+                    return;
+                }
+                if request.msg == OMsg::Quit {
+                    log::info!("From secondary thread. Quitting after receiving OMsg::Quit");
+                    return;
+                }
+                let id = request.id;
+                if !self.in_flight.start(id, self.connection.cancel_handle()) {
+                    log::info!("Dropping request {} ({:?}), cancelled", id, request.msg);
+                    continue;
+                }
+                // replies echo the kind of the request, and the widget instance
+                // it was made for
+                mailbox.address(&request);
+                let timeout = self.arm(&request);
+                let outcome = self.isolate(&request, &mut results, conductor, mailbox);
+                let stop = self.in_flight.finish(id);
+                if outcome == Outcome::Cancelled {
+                    // the error does not say who cancelled the query, but we know
+                    match stop {
+                        Some(Stop::Cancelled) => log::info!("Request {} cancelled", id),
+                        Some(Stop::TimedOut) => timed_out(&request, timeout, conductor, mailbox),
+                        None => cancelled_by_server(&request, conductor, mailbox),
                     }
-                    OMsg::PackageWiths(msg) => {
-                        match_package_withs(msg, &mut db, &mut conductor, &sender);
+                }
+                mailbox.release(id);
+                // the request may have written to the database, making cached
+                // results stale
+                if outcome == Outcome::Handled {
+                    for kind in request.msg.invalidates() {
+                        self.cache.invalidate(kind);
                     }
-                    OMsg::MainToolbar(msg) => {
-                        match_main_toolbar(msg, &mut db, &mut conductor, &sender);
+                    for kind in self.invalidated.drain(..) {
+                        self.cache.invalidate(kind);
                     }
-                    OMsg::Quit => {
-                        log::info!("From secondary thread. Quitting after receiving OMsg::Quit");
-                        // try break instead of return
-                        break;
+                } else {
+                    self.invalidated.clear();
+                }
+                // retry the request, along with the rest of its batch, once the
+                // connection has been re-established
+                if let Outcome::ConnectionLost(reason) = outcome {
+                    self.connection.disconnected(reason, conductor, mailbox);
+                    for unanswered in batch.rev() {
+                        self.pending.push_front(unanswered);
                     }
+                    self.pending.push_front(request);
+                    break;
                 }
             }
-        });
-        // the application needs to show and execute before the thread handle is joined
-        // so that the scope lives longer than the application
-        unsafe {
-            main_window.show();
-            result = QApplication::exec();
         }
-        let _res = handle.join().expect("problem joining scoped thread handle");
-    })
-    .expect("problem with scoped channel");
-    result
+    }
+
+    // send the deadline of the request to the watchdog, if its query has a
+    // timeout, returning the timeout
+    fn arm(&self, request: &Request) -> Option<Duration> {
+        let timeout = Query::of(&request.msg).and_then(|query| self.timeouts.timeout(query.kind()));
+        if let Some(timeout) = timeout {
+            // the watchdog lives as long as we do, so the send cannot fail
+            let _ = self.deadlines.send((request.id, Instant::now() + timeout));
+        }
+        timeout
+    }
+
+    // drop the outstanding requests, having been told to quit without draining
+    fn abandon(&mut self) {
+        self.pending.extend(self.receiver.try_iter());
+        let abandoned = self
+            .pending
+            .drain(..)
+            .filter(|request| request.msg != OMsg::Quit)
+            .count();
+        log::info!(
+            "From secondary thread. Quitting, abandoning {} queued request(s)",
+            abandoned
+        );
+    }
+
+    // handle the request, replying with an error should handling it panic, so
+    // that one bad request does not take down the secondary thread
+    fn isolate<N: Notifier>(
+        &mut self,
+        request: &Request,
+        results: &mut Results,
+        conductor: &mut N,
+        mailbox: &Mailbox,
+    ) -> Outcome {
+        let handled = catch_unwind(AssertUnwindSafe(|| {
+            self.handle(request.msg.clone(), request.id, results, conductor, mailbox)
+        }));
+        match handled {
+            Ok(outcome) => outcome,
+            Err(panic) => {
+                let panic = panic_message(&*panic);
+                log::error!(
+                    "Panicked while handling request {} ({:?}): {}",
+                    request.id,
+                    request.msg,
+                    panic
+                );
+                let error = IError::new(
+                    request.msg.family(),
+                    ErrorCategory::Internal,
+                    format!("Panicked while handling {:?}: {}", request.msg, panic),
+                );
+                report(error.with_request(request.id), conductor, mailbox);
+                Outcome::Handled
+            }
+        }
+    }
+
+    fn handle<N: Notifier>(
+        &mut self,
+        msg: OMsg,
+        id: RequestId,
+        results: &mut Results,
+        conductor: &mut N,
+        mailbox: &Mailbox,
+    ) -> Outcome {
+        match msg {
+            OMsg::GetStats => {
+                let reply = IMsg::Stats(self.stats.clone());
+                deliver(reply, Event::Stats, Some(id), conductor, mailbox);
+                Outcome::Handled
+            }
+            // the intake acts upon cancellations, so they do not reach us.
+            // invalidation follows handling, and Quit is handled by `run`
+            OMsg::Cancel(_) | OMsg::InvalidateCache(_) | OMsg::Quit => Outcome::Handled,
+            // the rest belong to a family, and are handed to its handler
+            msg => {
+                let family = msg.family();
+                let mut ctx = Context {
+                    request: id,
+                    connection: &mut self.connection,
+                    results,
+                    cache: &mut self.cache,
+                    stats: &mut self.stats,
+                    current_site: &mut self.current_site,
+                    invalidated: &mut self.invalidated,
+                    conductor,
+                    mailbox,
+                };
+                self.handlers.handle(msg, &mut ctx).unwrap_or_else(|| {
+                    let message = format!("No handler is registered for the {} family", family);
+                    ctx.report(IError::new(family, ErrorCategory::Internal, message));
+                    Outcome::Handled
+                })
+            }
+        }
+    }
 }
+
+// answer a request which was cancelled by the watchdog, having run past its timeout
+fn timed_out<N: Notifier>(
+    request: &Request,
+    timeout: Option<Duration>,
+    conductor: &mut N,
+    mailbox: &Mailbox,
+) {
+    let timeout = timeout.unwrap_or_default();
+    log::warn!(
+        "Request {} ({:?}) timed out after {:?}",
+        request.id,
+        request.msg,
+        timeout
+    );
+    let error = IError::new(
+        request.msg.family(),
+        ErrorCategory::Timeout,
+        format!("Timed out after {:?} handling {:?}", timeout, request.msg),
+    );
+    report(error.with_request(request.id), conductor, mailbox);
+}
+
+// answer a request whose query the server cancelled of its own accord, most
+// likely for exceeding its statement_timeout
+fn cancelled_by_server<N: Notifier>(request: &Request, conductor: &mut N, mailbox: &Mailbox) {
+    log::warn!(
+        "Request {} ({:?}) cancelled by the server",
+        request.id,
+        request.msg
+    );
+    let mut error = IError::new(
+        request.msg.family(),
+        ErrorCategory::Timeout,
+        format!(
+            "The server cancelled the query handling {:?}, eg for exceeding its statement_timeout",
+            request.msg
+        ),
+    );
+    error.code = Some(SqlState::QUERY_CANCELED.code().to_string());
+    report(error.with_request(request.id), conductor, mailbox);
+}
+
+/// The result of handling a single request in the secondary thread
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The request was answered, successfully or with an error
+    Handled,
+    /// The connection to the database was lost. The request should be retried
+    /// after reconnecting.
+    ConnectionLost(String),
+    /// The query was cancelled. Whether at the application's request, in which
+    /// case it goes unanswered, by the watchdog, or by the server, in which cases
+    /// it is answered with an `ErrorCategory::Timeout` error, is for the
+    /// secondary thread to tell.
+    Cancelled,
+}
+
+/// Report a failed query to the ui via the family's Error event, unless the failure
+/// was caused by a broken connection, in which case the caller is told to
+/// reconnect and retry, or the query was cancelled, which is recognized by its
+/// SQLSTATE, 57014.
+///
+/// # Arguments
+/// * `description` - Describes the failed query, eg "Unable to get roles from db"
+/// * `err` - The error returned by the query
+/// * `family` - The family of the request which made the query
+/// * `request` - The id of the request which made the query
+/// * `conductor` - Signals the family's Error event
+/// * `mailbox` - Holds the family's Error IMsg for the ui thread
+pub(crate) fn query_failed<N: Notifier>(
+    description: &str,
+    err: BackendError,
+    family: &'static str,
+    request: RequestId,
+    conductor: &mut N,
+    mailbox: &Mailbox,
+) -> Outcome {
+    let message = err.to_string();
+    if is_connection_error(err.as_ref()) {
+        return Outcome::ConnectionLost(message);
+    }
+    if is_cancellation(err.as_ref()) {
+        log::info!("Request {} cancelled: {}", request, message);
+        return Outcome::Cancelled;
+    }
+    report(
+        IError::from_backend(family, description, &err).with_request(request),
+        conductor,
+        mailbox,
+    );
+    Outcome::Handled
+}
+
+/// Report `error` to the family of the request which failed, eg as an
+/// IVpinDialog::Error signaled with VpinDialog::Error. Errors belonging to no
+/// family, such as those of control messages, are reported via Event::Error.
+pub(crate) fn report<N: Notifier>(error: IError, conductor: &mut N, mailbox: &Mailbox) {
+    let request = error.request;
+    let (msg, event) = match error.family {
+        OVpinDialog::FAMILY => (
+            IVpinDialog::Error(error).to_imsg(),
+            VpinDialog::Error.to_event(),
+        ),
+        OPackagesTree::FAMILY => (
+            IPackagesTree::Error(error).to_imsg(),
+            PackagesTree::Error.to_event(),
+        ),
+        OPackageWiths::FAMILY => (
+            IPackageWiths::Error(error).to_imsg(),
+            PackageWiths::Error.to_event(),
+        ),
+        OMainToolbar::FAMILY => (
+            IMainToolbar::Error(error).to_imsg(),
+            MainToolbar::Error.to_event(),
+        ),
+        OConnection::FAMILY => (
+            IConnection::Error(error).to_imsg(),
+            Connection::Error.to_event(),
+        ),
+        _ => (IMsg::Error(error), Event::Error),
+    };
+    deliver(msg, event, request, conductor, mailbox);
+}
+
+/// Post `msg` to the mailbox and signal `event`, along with the sequence
+/// number `msg` was posted under. Both echo the id of the request being
+/// answered, if any.
+pub(crate) fn deliver<N: Notifier>(
+    msg: IMsg,
+    event: Event,
+    request: Option<RequestId>,
+    conductor: &mut N,
+    mailbox: &Mailbox,
+) {
+    let seq = mailbox.post(request, msg);
+    conductor.signal(Signal::new(seq, request, event));
+}
+
 /// Create the slot that handles terminating the secondary thread when
 /// the application is about to quit. This function will also wire up
 /// the appropriate signal & slot to handle this.
@@ -109,13 +817,994 @@ pub fn create(
 ///
 /// # Returns
 /// * the slot designed to terminate the secondary thread
-pub fn create_quit_slot<'a>(to_thread_sender: Sender<OMsg>, app: MutPtr<QApplication>) -> Slot<'a> {
+#[cfg(feature = "qt")]
+pub fn create_quit_slot<'a>(
+    to_thread_sender: Sender<Request>,
+    app: MutPtr<QApplication>,
+) -> Slot<'a> {
     let quit_slot = Slot::new(move || {
         log::info!("Sending secondary thread termination request ");
-        to_thread_sender.send(OMsg::Quit).expect("couldn't send");
+        to_thread_sender
+            .send(OMsg::Quit.into())
+            .expect("couldn't send");
     });
     unsafe {
         app.about_to_quit().connect(&quit_slot);
     }
     quit_slot
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backend::MemoryBackend, notifier::ChannelNotifier, CustomMsg, InstanceId, ToOMsg};
+
+    struct Harness {
+        to_thread: Sender<Request>,
+        signals: Receiver<Signal>,
+        mailbox: Mailbox,
+        handle: JoinHandle<()>,
+    }
+
+    impl Harness {
+        fn new(backend: MemoryBackend) -> Self {
+            Self::with_config(backend, WorkerConfig::default())
+        }
+
+        fn with_config(backend: MemoryBackend, config: WorkerConfig) -> Self {
+            Self::with_handlers(backend, config, Handlers::new())
+        }
+
+        fn with_handlers(
+            backend: MemoryBackend,
+            config: WorkerConfig,
+            handlers: Handlers<MemoryBackend>,
+        ) -> Self {
+            let (to_thread, from_ui) = crossbeam_channel::unbounded();
+            let (signal_sender, signals) = crossbeam_channel::unbounded();
+            let mailbox = Mailbox::new();
+            let handle = spawn_worker_with_handlers(
+                backend,
+                config,
+                handlers,
+                ChannelNotifier::new(signal_sender),
+                mailbox.clone(),
+                from_ui,
+            );
+            Self {
+                to_thread,
+                signals,
+                mailbox,
+                handle,
+            }
+        }
+
+        fn next(&self) -> (Event, IMsg) {
+            let timeout = Duration::from_secs(5);
+            let signal = self.signals.recv_timeout(timeout).expect("no signal");
+            let reply = self.mailbox.collect(&signal).expect("no imsg");
+            assert_eq!(reply.request, signal.request);
+            (signal.event, reply.msg)
+        }
+
+        fn expect_connected(&self) {
+            loop {
+                if let (Event::Connection(Connection::Connected), _) = self.next() {
+                    return;
+                }
+            }
+        }
+
+        fn quit(self) {
+            self.to_thread.send(OMsg::Quit.into()).unwrap();
+            self.handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn can_answer_requests_headless() {
+        let backend = MemoryBackend::new();
+        backend.set_roles(&["model", "anim"]);
+        let harness = Harness::new(backend);
+        harness.expect_connected();
+        harness
+            .to_thread
+            .send(OVpinDialog::GetRoles.to_request())
+            .unwrap();
+        match harness.next() {
+            (
+                Event::VpinDialog(VpinDialog::UpdateRoles),
+                IMsg::VpinDialog(IVpinDialog::Roles(roles)),
+            ) => {
+                assert_eq!(roles, vec!["model", "anim"])
+            }
+            _ => panic!("expected roles"),
+        }
+        harness.quit();
+    }
+
+    #[test]
+    fn replies_echo_the_request_id() {
+        let harness = Harness::new(MemoryBackend::new());
+        harness.expect_connected();
+        let request = OMainToolbar::GetRoles.to_request();
+        let id = request.id;
+        harness.to_thread.send(request).unwrap();
+        let signal = harness
+            .signals
+            .recv_timeout(Duration::from_secs(5))
+            .expect("no signal");
+        assert_eq!(signal.request, Some(id));
+        let reply = harness.mailbox.collect(&signal).unwrap();
+        assert_eq!(reply.request, Some(id));
+        assert!(matches!(
+            reply.msg,
+            IMsg::MainToolbar(IMainToolbar::Roles(_))
+        ));
+        assert_eq!(reply.instance, None);
+        // as do the replies to requests made on behalf of a widget instance
+        let instance = InstanceId::from(2);
+        harness
+            .to_thread
+            .send(OMainToolbar::GetSites.to_request_for(instance))
+            .unwrap();
+        let signal = harness
+            .signals
+            .recv_timeout(Duration::from_secs(5))
+            .expect("no signal");
+        let reply = harness.mailbox.collect(&signal).unwrap();
+        assert_eq!(reply.instance, Some(instance));
+        harness.quit();
+    }
+
+    #[test]
+    fn query_errors_are_reported() {
+        let backend = MemoryBackend::new();
+        let harness = Harness::new(backend.clone());
+        harness.expect_connected();
+        backend.fail_next("relation \"site\" does not exist");
+        harness
+            .to_thread
+            .send(OPackagesTree::GetSites.to_request())
+            .unwrap();
+        match harness.next() {
+            (
+                Event::PackagesTree(PackagesTree::Error),
+                IMsg::PackagesTree(IPackagesTree::Error(err)),
+            ) => {
+                assert!(err.message.contains("does not exist"));
+                assert_eq!(err.family, "PackagesTree");
+                assert_eq!(err.category, ErrorCategory::Query);
+                assert!(err.request.is_some());
+                assert!(!err.retryable);
+            }
+            _ => panic!("expected an error"),
+        }
+        harness.quit();
+    }
+
+    #[test]
+    fn queries_cancelled_by_the_server_are_reported_as_timeouts() {
+        let backend = MemoryBackend::new();
+        let harness = Harness::new(backend.clone());
+        harness.expect_connected();
+        // the memory backend is judged by its message. we did not cancel the query,
+        // so the server did
+        backend.fail_next("canceling statement due to user request");
+        let request = OPackagesTree::GetSites.to_request();
+        let id = request.id;
+        harness.to_thread.send(request).unwrap();
+        match harness.next() {
+            (
+                Event::PackagesTree(PackagesTree::Error),
+                IMsg::PackagesTree(IPackagesTree::Error(err)),
+            ) => {
+                assert_eq!(err.category, ErrorCategory::Timeout);
+                assert_eq!(err.code.as_deref(), Some("57014"));
+                assert_eq!(err.request, Some(id));
+            }
+            _ => panic!("expected a PackagesTree timeout"),
+        }
+        harness.quit();
+    }
+
+    #[test]
+    fn errors_are_reported_to_the_requesting_family() {
+        let harness = Harness::new(MemoryBackend::new());
+        harness.expect_connected();
+        harness
+            .to_thread
+            .send(OVpinDialog::GetLevels("dev03".to_string()).to_request())
+            .unwrap();
+        match harness.next() {
+            (Event::VpinDialog(VpinDialog::Error), IMsg::VpinDialog(IVpinDialog::Error(err))) => {
+                assert_eq!(err.family, "VpinDialog");
+                assert!(err.message.contains("Unknown show: dev03"));
+            }
+            _ => panic!("expected a VpinDialog error"),
+        }
+        harness.quit();
+    }
+
+    #[test]
+    fn requests_are_retried_after_reconnecting() {
+        let backend = MemoryBackend::new();
+        backend.set_packages(&["houdini"]);
+        let harness = Harness::new(backend.clone());
+        harness.expect_connected();
+        backend.fail_next("connection closed");
+        harness
+            .to_thread
+            .send(OPackageWiths::GetPackages.to_request())
+            .unwrap();
+        assert!(matches!(
+            harness.next(),
+            (
+                Event::Connection(Connection::Disconnected),
+                IMsg::Connection(IConnection::Disconnected(_))
+            )
+        ));
+        harness.expect_connected();
+        match harness.next() {
+            (
+                Event::PackageWiths(PackageWiths::GetPackages),
+                IMsg::PackageWiths(IPackageWiths::Packages(packages)),
+            ) => assert_eq!(packages, vec!["houdini"]),
+            _ => panic!("expected packages"),
+        }
+        harness.quit();
+    }
+
+    #[test]
+    fn connection_failures_are_retried() {
+        let backend = MemoryBackend::new();
+        backend.fail_connects(1);
+        let harness = Harness::new(backend);
+        assert!(matches!(
+            harness.next(),
+            (Event::Connection(Connection::Connecting), _)
+        ));
+        assert!(matches!(
+            harness.next(),
+            (
+                Event::Connection(Connection::Retrying),
+                IMsg::Connection(IConnection::Retrying { attempt: 1, .. })
+            )
+        ));
+        harness.expect_connected();
+        harness.quit();
+    }
+
+    #[test]
+    fn requests_dropped_while_disconnected_are_reported_to_their_instance() {
+        let backend = MemoryBackend::new();
+        backend.fail_connects(u32::max_value());
+        let config = WorkerConfig {
+            reconnect: ReconnectPolicy {
+                initial_delay: Duration::from_millis(50),
+                max_delay: Duration::from_millis(50),
+                max_queued: 1,
+                ..ReconnectPolicy::default()
+            },
+            ..WorkerConfig::default()
+        };
+        let harness = Harness::with_config(backend, config);
+        let instance = InstanceId::from(7);
+        let dropped = OVpinDialog::GetRoles.to_request_for(instance);
+        let dropped_id = dropped.id;
+        harness.to_thread.send(dropped).unwrap();
+        harness
+            .to_thread
+            .send(OVpinDialog::GetSites.to_request_for(instance))
+            .unwrap();
+        loop {
+            let signal = harness
+                .signals
+                .recv_timeout(Duration::from_secs(5))
+                .expect("no signal");
+            let reply = harness.mailbox.collect(&signal).expect("no imsg");
+            if let IMsg::VpinDialog(IVpinDialog::Error(err)) = reply.msg {
+                assert_eq!(err.request, Some(dropped_id));
+                assert_eq!(reply.request, Some(dropped_id));
+                assert_eq!(reply.instance, Some(instance));
+                assert_eq!(reply.kind, Some(OVpinDialog::GetRoles.kind()));
+                break;
+            }
+        }
+        harness.quit();
+    }
+
+    #[test]
+    fn quit_answers_requests_queued_while_disconnected_when_draining() {
+        let backend = MemoryBackend::new();
+        backend.fail_connects(u32::max_value());
+        let config = WorkerConfig {
+            shutdown: ShutdownPolicy::Drain,
+            ..WorkerConfig::default()
+        };
+        let harness = Harness::with_config(backend, config);
+        let requests = vec![
+            OVpinDialog::GetRoles.to_request_for(InstanceId::from(1)),
+            OPackageWiths::GetPackages.to_request(),
+        ];
+        let ids = requests.iter().map(|r| r.id).collect::<Vec<_>>();
+        for request in requests {
+            harness.to_thread.send(request).unwrap();
+        }
+        let Harness {
+            to_thread,
+            signals,
+            mailbox,
+            handle,
+        } = harness;
+        assert_eq!(
+            shutdown_worker(&to_thread, handle, Duration::from_secs(5)),
+            Ok(())
+        );
+        let abandoned = signals
+            .try_iter()
+            .filter_map(|signal| {
+                let reply = mailbox.collect(&signal).expect("no imsg");
+                match reply.msg {
+                    IMsg::VpinDialog(IVpinDialog::Error(err))
+                    | IMsg::PackageWiths(IPackageWiths::Error(err)) => {
+                        assert_eq!(err.category, ErrorCategory::Connection);
+                        Some((err.request, reply.instance))
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            abandoned,
+            vec![
+                (Some(ids[0]), Some(InstanceId::from(1))),
+                (Some(ids[1]), None)
+            ]
+        );
+    }
+
+    // wait until the backend has started running the named query
+    fn wait_for_query(backend: &MemoryBackend, query: &str) {
+        while !backend.queries().iter().any(|q| q == query) {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn queued_requests_are_superseded() {
+        let backend = MemoryBackend::new();
+        backend.set_levels("dev01", &[]);
+        backend.set_levels("dev02", &["dev02.rd"]);
+        let harness = Harness::new(backend.clone());
+        harness.expect_connected();
+        backend.hold_next(Duration::from_millis(200));
+        harness
+            .to_thread
+            .send(OVpinDialog::GetRoles.to_request())
+            .unwrap();
+        wait_for_query(&backend, "roles");
+        for show in &["dev01", "dev02"] {
+            harness
+                .to_thread
+                .send(OVpinDialog::GetLevels(show.to_string()).to_request())
+                .unwrap();
+        }
+        assert!(matches!(
+            harness.next(),
+            (Event::VpinDialog(VpinDialog::UpdateRoles), _)
+        ));
+        match harness.next() {
+            (
+                Event::VpinDialog(VpinDialog::UpdateLevels),
+                IMsg::VpinDialog(IVpinDialog::Levels(levels)),
+            ) => assert!(levels.contains_key("rd")),
+            _ => panic!("expected levels"),
+        }
+        assert!(!backend.queries().iter().any(|q| q == "levels:dev01"));
+        harness.quit();
+    }
+
+    #[test]
+    fn replies_to_running_requests_superseded_since_are_recognized() {
+        let backend = MemoryBackend::new();
+        backend.set_levels("dev01", &["dev01.rd"]);
+        backend.set_levels("dev02", &["dev02.rd"]);
+        let harness = Harness::new(backend.clone());
+        harness.expect_connected();
+        backend.hold_next(Duration::from_millis(200));
+        harness
+            .to_thread
+            .send(OVpinDialog::GetLevels("dev01".to_string()).to_request())
+            .unwrap();
+        wait_for_query(&backend, "levels:dev01");
+        harness
+            .to_thread
+            .send(OVpinDialog::GetLevels("dev02".to_string()).to_request())
+            .unwrap();
+        // the first request is running, so is answered, but stale
+        let mut superseded = Vec::new();
+        for _ in 0..2 {
+            let signal = harness
+                .signals
+                .recv_timeout(Duration::from_secs(5))
+                .expect("no signal");
+            let reply = harness.mailbox.collect(&signal).unwrap();
+            assert_eq!(reply.kind, Some("VpinDialog::GetLevels"));
+            superseded.push(harness.mailbox.is_superseded(&reply));
+        }
+        assert_eq!(superseded, vec![true, false]);
+        harness.quit();
+    }
+
+    #[test]
+    fn queued_requests_for_other_instances_are_each_answered() {
+        let backend = MemoryBackend::new();
+        backend.set_roles(&["anim", "model"]);
+        let harness = Harness::new(backend.clone());
+        harness.expect_connected();
+        backend.hold_next(Duration::from_millis(200));
+        harness
+            .to_thread
+            .send(OMainToolbar::GetShows.to_request())
+            .unwrap();
+        wait_for_query(&backend, "shows");
+        let instances = vec![InstanceId::from(1), InstanceId::from(2)];
+        for instance in &instances {
+            harness
+                .to_thread
+                .send(OVpinDialog::GetRoles.to_request_for(*instance))
+                .unwrap();
+        }
+        assert!(matches!(
+            harness.next(),
+            (Event::MainToolbar(MainToolbar::GetShows), _)
+        ));
+        let mut answered = Vec::new();
+        for _ in &instances {
+            let signal = harness
+                .signals
+                .recv_timeout(Duration::from_secs(5))
+                .expect("no signal");
+            assert_eq!(signal.event, Event::VpinDialog(VpinDialog::UpdateRoles));
+            let reply = harness.mailbox.collect(&signal).unwrap();
+            assert!(matches!(reply.msg, IMsg::VpinDialog(IVpinDialog::Roles(_))));
+            answered.push(reply.instance.expect("no instance"));
+        }
+        assert_eq!(answered, instances);
+        harness.quit();
+    }
+
+    #[test]
+    fn queued_requests_share_identical_queries() {
+        let backend = MemoryBackend::new();
+        backend.set_sites(&["portland", "montreal"]);
+        let harness = Harness::new(backend.clone());
+        harness.expect_connected();
+        backend.hold_next(Duration::from_millis(200));
+        harness
+            .to_thread
+            .send(OMainToolbar::GetShows.to_request())
+            .unwrap();
+        wait_for_query(&backend, "shows");
+        let requests = vec![
+            OPackagesTree::GetSites.to_request(),
+            OVpinDialog::GetSites.to_request(),
+            OMainToolbar::GetSites.to_request(),
+        ];
+        let ids = requests.iter().map(|r| r.id).collect::<Vec<_>>();
+        for request in requests {
+            harness.to_thread.send(request).unwrap();
+        }
+        assert!(matches!(
+            harness.next(),
+            (Event::MainToolbar(MainToolbar::GetShows), _)
+        ));
+        // each family is answered, in the order it asked
+        for id in ids {
+            let signal = harness
+                .signals
+                .recv_timeout(Duration::from_secs(5))
+                .expect("no signal");
+            assert_eq!(signal.request, Some(id));
+            match harness.mailbox.collect(&signal).unwrap().msg {
+                IMsg::PackagesTree(IPackagesTree::Sites { sites, .. })
+                | IMsg::VpinDialog(IVpinDialog::Sites(sites))
+                | IMsg::MainToolbar(IMainToolbar::Sites(sites)) => {
+                    assert_eq!(sites, vec!["portland", "montreal"])
+                }
+                _ => panic!("expected sites"),
+            }
+        }
+        let sites = backend.queries().iter().filter(|q| *q == "sites").count();
+        assert_eq!(sites, 1);
+        harness.quit();
+    }
+
+    #[test]
+    fn cached_results_are_reused_until_invalidated() {
+        let backend = MemoryBackend::new();
+        let harness = Harness::new(backend.clone());
+        harness.expect_connected();
+        let requests = vec![
+            OVpinDialog::GetSites.to_request(),
+            OMainToolbar::GetSites.to_request(),
+            OMsg::InvalidateCache(QueryKind::Sites).into(),
+            OPackagesTree::GetSites.to_request(),
+        ];
+        for request in requests {
+            // the invalidation goes unanswered
+            let answered = request.msg.is_supersedable();
+            harness.to_thread.send(request).unwrap();
+            if answered {
+                harness.next();
+            }
+        }
+        let sites = backend.queries().iter().filter(|q| *q == "sites").count();
+        assert_eq!(sites, 2);
+        harness.quit();
+    }
+
+    #[test]
+    fn writes_invalidate_the_results_they_make_stale() {
+        let backend = MemoryBackend::new();
+        let handlers = Handlers::new().register("Roles", |msg, ctx| {
+            // a handler may also invalidate as it goes
+            if let OMsg::Custom(msg) = msg {
+                if msg.name() == "AddPlatform" {
+                    ctx.invalidate(QueryKind::Platforms);
+                }
+            }
+            ctx.reply(CustomMsg::new("Roles", "Written", ()));
+            Outcome::Handled
+        });
+        let harness = Harness::with_handlers(backend.clone(), WorkerConfig::default(), handlers);
+        harness.expect_connected();
+        let requests = vec![
+            OMainToolbar::GetRoles.to_request(),
+            OMainToolbar::GetPlatforms.to_request(),
+            CustomMsg::new("Roles", "AddRole", "lighting".to_string())
+                .invalidating(vec![QueryKind::Roles])
+                .to_request(),
+            CustomMsg::new("Roles", "AddPlatform", "cent7".to_string()).to_request(),
+            OMainToolbar::GetRoles.to_request(),
+            OMainToolbar::GetPlatforms.to_request(),
+        ];
+        for request in requests {
+            harness.to_thread.send(request).unwrap();
+            harness.next();
+        }
+        let count = |query: &str| backend.queries().iter().filter(|q| *q == query).count();
+        assert_eq!(count("roles"), 2);
+        assert_eq!(count("platforms"), 2);
+        harness.quit();
+    }
+
+    #[test]
+    fn running_requests_can_be_cancelled() {
+        let backend = MemoryBackend::new();
+        backend.set_roles(&["anim"]);
+        let harness = Harness::new(backend.clone());
+        harness.expect_connected();
+        backend.hold_next(Duration::from_secs(30));
+        let request = OPackagesTree::GetPackages.to_request();
+        let id = request.id;
+        harness.to_thread.send(request).unwrap();
+        wait_for_query(&backend, "packages");
+        harness.to_thread.send(OMsg::Cancel(id).into()).unwrap();
+        // the cancelled request goes unanswered, and the next is answered promptly
+        harness
+            .to_thread
+            .send(OVpinDialog::GetRoles.to_request())
+            .unwrap();
+        assert!(matches!(
+            harness.next(),
+            (Event::VpinDialog(VpinDialog::UpdateRoles), _)
+        ));
+        assert!(backend.queries().iter().any(|q| q == "cancel"));
+        harness.quit();
+    }
+
+    #[test]
+    fn the_current_site_is_sent_with_the_sites() {
+        let backend = MemoryBackend::new();
+        backend.set_sites(&["portland", "montreal"]);
+        let config = WorkerConfig {
+            site: SitePolicy::new().with_site("montreal"),
+            ..WorkerConfig::default()
+        };
+        let harness = Harness::with_config(backend, config);
+        harness.expect_connected();
+        let requests = vec![
+            OPackagesTree::GetSites.to_request(),
+            OPackagesTree::SetCurrentSite("portland".to_string()).to_request(),
+            OPackagesTree::GetSites.to_request(),
+        ];
+        let mut currents = Vec::new();
+        for request in requests {
+            // wait for each answer, lest the later GetSites supersede the first
+            harness.to_thread.send(request).unwrap();
+            match harness.next() {
+                (
+                    Event::PackagesTree(PackagesTree::GetSites),
+                    IMsg::PackagesTree(IPackagesTree::Sites { sites, current }),
+                ) => {
+                    assert_eq!(sites, vec!["portland", "montreal"]);
+                    currents.push(current);
+                }
+                _ => panic!("expected sites"),
+            }
+        }
+        // the override outlasts the request which made it
+        assert_eq!(currents, vec!["montreal", "portland", "portland"]);
+        harness.quit();
+    }
+
+    #[test]
+    fn slow_queries_time_out() {
+        let backend = MemoryBackend::new();
+        backend.set_levels("dev01", &["dev01.rd", "dev01.rd.0001"]);
+        let config = WorkerConfig {
+            timeouts: TimeoutPolicy::none()
+                .with_default(Duration::from_secs(30))
+                .with_timeout(QueryKind::Levels, Duration::from_millis(50)),
+            ..WorkerConfig::default()
+        };
+        let harness = Harness::with_config(backend.clone(), config);
+        harness.expect_connected();
+        backend.hold_next(Duration::from_secs(30));
+        let request = OVpinDialog::GetLevels("dev01".to_string()).to_request();
+        let id = request.id;
+        harness.to_thread.send(request).unwrap();
+        match harness.next() {
+            (Event::VpinDialog(VpinDialog::Error), IMsg::VpinDialog(IVpinDialog::Error(err))) => {
+                assert_eq!(err.category, ErrorCategory::Timeout);
+                assert_eq!(err.request, Some(id));
+                assert!(err.retryable);
+            }
+            _ => panic!("expected a VpinDialog timeout"),
+        }
+        // retrying succeeds once the query is quick again
+        harness
+            .to_thread
+            .send(OVpinDialog::GetLevels("dev01".to_string()).to_request())
+            .unwrap();
+        assert!(matches!(
+            harness.next(),
+            (Event::VpinDialog(VpinDialog::UpdateLevels), _)
+        ));
+        harness.quit();
+    }
+
+    #[test]
+    fn panics_are_reported_to_the_requesting_family() {
+        let backend = MemoryBackend::new();
+        backend.set_roles(&["anim"]);
+        let harness = Harness::new(backend.clone());
+        harness.expect_connected();
+        backend.panic_next("bad row");
+        harness
+            .to_thread
+            .send(OVpinDialog::GetSites.to_request())
+            .unwrap();
+        match harness.next() {
+            (Event::VpinDialog(VpinDialog::Error), IMsg::VpinDialog(IVpinDialog::Error(err))) => {
+                assert_eq!(err.category, ErrorCategory::Internal);
+                assert!(err.message.contains("bad row"));
+            }
+            _ => panic!("expected a VpinDialog error"),
+        }
+        // the secondary thread carries on
+        harness
+            .to_thread
+            .send(OVpinDialog::GetRoles.to_request())
+            .unwrap();
+        assert!(matches!(
+            harness.next(),
+            (Event::VpinDialog(VpinDialog::UpdateRoles), _)
+        ));
+        harness.quit();
+    }
+
+    #[test]
+    fn worker_restarts_after_panicking() {
+        let backend = MemoryBackend::new();
+        backend.set_packages(&["houdini"]);
+        let harness = Harness::new(backend.clone());
+        harness.expect_connected();
+        // lose the connection, then panic while reconnecting
+        backend.fail_next("connection closed");
+        backend.panic_connects(1);
+        harness
+            .to_thread
+            .send(OPackageWiths::GetPackages.to_request())
+            .unwrap();
+        loop {
+            if let (Event::Error, IMsg::Error(err)) = harness.next() {
+                assert_eq!(err.category, ErrorCategory::Internal);
+                break;
+            }
+        }
+        // the queued request survives the restart
+        harness.expect_connected();
+        assert!(matches!(
+            harness.next(),
+            (Event::PackageWiths(PackageWiths::GetPackages), _)
+        ));
+        harness.quit();
+    }
+
+    #[test]
+    fn quit_abandons_outstanding_requests() {
+        let backend = MemoryBackend::new();
+        let harness = Harness::new(backend.clone());
+        harness.expect_connected();
+        backend.hold_next(Duration::from_secs(30));
+        harness
+            .to_thread
+            .send(OPackageWiths::GetPackages.to_request())
+            .unwrap();
+        wait_for_query(&backend, "packages");
+        harness
+            .to_thread
+            .send(OVpinDialog::GetRoles.to_request())
+            .unwrap();
+        // the running query is cancelled rather than waited upon
+        let Harness {
+            to_thread, handle, ..
+        } = harness;
+        assert_eq!(
+            shutdown_worker(&to_thread, handle, Duration::from_secs(5)),
+            Ok(())
+        );
+        let queries = backend.queries();
+        assert!(queries.iter().any(|q| q == "cancel"));
+        assert!(!queries.iter().any(|q| q == "roles"));
+    }
+
+    #[test]
+    fn quit_drains_outstanding_requests_when_configured() {
+        let backend = MemoryBackend::new();
+        let config = WorkerConfig {
+            shutdown: ShutdownPolicy::Drain,
+            ..WorkerConfig::default()
+        };
+        let harness = Harness::with_config(backend.clone(), config);
+        harness.expect_connected();
+        backend.hold_next(Duration::from_millis(200));
+        harness
+            .to_thread
+            .send(OPackageWiths::GetPackages.to_request())
+            .unwrap();
+        wait_for_query(&backend, "packages");
+        harness
+            .to_thread
+            .send(OVpinDialog::GetRoles.to_request())
+            .unwrap();
+        let Harness {
+            to_thread,
+            signals,
+            handle,
+            ..
+        } = harness;
+        assert_eq!(
+            shutdown_worker(&to_thread, handle, Duration::from_secs(5)),
+            Ok(())
+        );
+        let events = signals.try_iter().map(|s| s.event).collect::<Vec<_>>();
+        assert!(events.contains(&PackageWiths::GetPackages.to_event()));
+        assert!(events.contains(&VpinDialog::UpdateRoles.to_event()));
+    }
+
+    #[test]
+    fn a_thread_which_does_not_stop_in_time_is_reported() {
+        let backend = MemoryBackend::new();
+        let config = WorkerConfig {
+            shutdown: ShutdownPolicy::Drain,
+            ..WorkerConfig::default()
+        };
+        let harness = Harness::with_config(backend.clone(), config);
+        harness.expect_connected();
+        backend.hold_next(Duration::from_millis(500));
+        harness
+            .to_thread
+            .send(OVpinDialog::GetRoles.to_request())
+            .unwrap();
+        wait_for_query(&backend, "roles");
+        let Harness {
+            to_thread, handle, ..
+        } = harness;
+        // the query is drained, so outlasts the timeout
+        let timeout = Duration::from_millis(20);
+        assert_eq!(
+            shutdown_worker(&to_thread, handle, timeout),
+            Err(ShutdownError::TimedOut(timeout))
+        );
+    }
+
+    #[test]
+    fn query_statistics_are_reported_and_dumped() {
+        let backend = MemoryBackend::new();
+        backend.set_roles(&["anim"]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stats.txt");
+        let config = WorkerConfig {
+            cache: CachePolicy::disabled(),
+            stats_file: Some(path.clone()),
+            ..WorkerConfig::default()
+        };
+        let harness = Harness::with_config(backend.clone(), config);
+        harness.expect_connected();
+        for _ in 0..2 {
+            harness
+                .to_thread
+                .send(OVpinDialog::GetRoles.to_request())
+                .unwrap();
+            harness.next();
+        }
+        harness
+            .to_thread
+            .send(OVpinDialog::GetLevels("dev03".to_string()).to_request())
+            .unwrap();
+        harness.next();
+        let request = Request::from(OMsg::GetStats);
+        let id = request.id;
+        harness.to_thread.send(request).unwrap();
+        let (event, msg) = harness.next();
+        assert_eq!(event, Event::Stats);
+        match msg {
+            IMsg::Stats(stats) => {
+                assert_eq!(stats.get(QueryKind::Roles).unwrap().count, 2);
+                let levels = stats.get(QueryKind::Levels).unwrap();
+                assert_eq!((levels.count, levels.errors), (1, 1));
+                assert_eq!(stats.get(QueryKind::Sites), None);
+            }
+            _ => panic!("expected stats in reply to request {}", id),
+        }
+        harness.quit();
+        let dumped = std::fs::read_to_string(&path).unwrap();
+        assert!(dumped.contains("roles: 2 queries, 0 errors"));
+        assert!(dumped.contains("levels: 1 queries, 1 errors"));
+    }
+
+    #[test]
+    fn custom_families_are_handed_to_their_handler() {
+        let backend = MemoryBackend::new();
+        backend.set_levels("dev01", &["dev01.rd", "dev01.rd.0001"]);
+        let handlers = Handlers::new().register("Shots", |msg, ctx| {
+            let show = match &msg {
+                OMsg::Custom(msg) => msg.payload::<String>().cloned().unwrap_or_default(),
+                _ => return Outcome::Handled,
+            };
+            let levels = ctx.db().levels(&show);
+            match levels {
+                Ok(levels) => {
+                    let shots = levels.len() - 1;
+                    ctx.reply(CustomMsg::new("Shots", "UpdateShots", shots));
+                    Outcome::Handled
+                }
+                Err(err) => ctx.query_failed("Unable to get shots", err, "Shots"),
+            }
+        });
+        let harness = Harness::with_handlers(backend, WorkerConfig::default(), handlers);
+        harness.expect_connected();
+        let request = CustomMsg::new("Shots", "GetShots", "dev01".to_string()).to_request();
+        let id = request.id;
+        harness.to_thread.send(request).unwrap();
+        match harness.next() {
+            (Event::Custom(event), IMsg::Custom(msg)) => {
+                assert_eq!(event, "Shots::UpdateShots");
+                assert_eq!(msg.payload::<usize>(), Some(&2));
+            }
+            _ => panic!("expected a reply to request {}", id),
+        }
+        // errors belonging to a custom family are reported via Event::Error
+        harness
+            .to_thread
+            .send(CustomMsg::new("Shots", "GetShots", "dev03".to_string()).to_request())
+            .unwrap();
+        match harness.next() {
+            (Event::Error, IMsg::Error(err)) => {
+                assert_eq!(err.family, "Shots");
+                assert_eq!(err.category, ErrorCategory::Query);
+            }
+            _ => panic!("expected a Shots error"),
+        }
+        harness.quit();
+    }
+
+    #[test]
+    fn custom_handlers_may_change_the_current_site() {
+        let backend = MemoryBackend::new();
+        backend.set_sites(&["portland", "montreal"]);
+        let handlers = Handlers::new().register("Travel", |msg, ctx| {
+            if let OMsg::Custom(msg) = &msg {
+                if let Some(site) = msg.payload::<String>() {
+                    ctx.set_current_site(site.as_str());
+                }
+            }
+            let arrived = ctx.current_site().to_string();
+            ctx.reply(CustomMsg::new("Travel", "Arrived", arrived));
+            Outcome::Handled
+        });
+        let harness = Harness::with_handlers(backend, WorkerConfig::default(), handlers);
+        harness.expect_connected();
+        let request = CustomMsg::new("Travel", "Go", "montreal".to_string()).to_request();
+        harness.to_thread.send(request).unwrap();
+        match harness.next() {
+            (Event::Custom(_), IMsg::Custom(msg)) => {
+                assert_eq!(
+                    msg.payload::<String>().map(String::as_str),
+                    Some("montreal")
+                )
+            }
+            _ => panic!("expected to arrive"),
+        }
+        harness
+            .to_thread
+            .send(OPackagesTree::GetSites.to_request())
+            .unwrap();
+        match harness.next() {
+            (_, IMsg::PackagesTree(IPackagesTree::Sites { current, .. })) => {
+                assert_eq!(current, "montreal")
+            }
+            _ => panic!("expected sites"),
+        }
+        harness.quit();
+    }
+
+    #[test]
+    fn requests_of_unregistered_families_are_reported() {
+        let harness = Harness::new(MemoryBackend::new());
+        harness.expect_connected();
+        harness
+            .to_thread
+            .send(CustomMsg::new("Shots", "GetShots", ()).to_request())
+            .unwrap();
+        match harness.next() {
+            (Event::Error, IMsg::Error(err)) => {
+                assert_eq!(err.family, "Shots");
+                assert_eq!(err.category, ErrorCategory::Internal);
+            }
+            _ => panic!("expected a Shots error"),
+        }
+        harness.quit();
+    }
+
+    #[test]
+    fn built_in_families_may_be_replaced() {
+        let handlers = Handlers::new().register(OVpinDialog::FAMILY, |_, ctx| {
+            let roles = vec!["replaced".to_string()];
+            ctx.deliver(
+                IVpinDialog::Roles(roles).to_imsg(),
+                VpinDialog::UpdateRoles.to_event(),
+            );
+            Outcome::Handled
+        });
+        let harness =
+            Harness::with_handlers(MemoryBackend::new(), WorkerConfig::default(), handlers);
+        harness.expect_connected();
+        harness
+            .to_thread
+            .send(OVpinDialog::GetRoles.to_request())
+            .unwrap();
+        match harness.next() {
+            (
+                Event::VpinDialog(VpinDialog::UpdateRoles),
+                IMsg::VpinDialog(IVpinDialog::Roles(roles)),
+            ) => {
+                assert_eq!(roles, vec!["replaced"]);
+            }
+            _ => panic!("expected the replacement's roles"),
+        }
+        harness.quit();
+    }
+
+    #[test]
+    fn worker_stops_when_application_hangs_up() {
+        let harness = Harness::new(MemoryBackend::new());
+        harness.expect_connected();
+        drop(harness.to_thread);
+        harness.handle.join().unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+//! Caches the results of queries whose answers seldom change, such as the roles,
+//! sites and platforms, so that opening a dialog does not go to the database.
+use super::shared::Query;
+use super::*;
+use crate::outgoing::QueryKind;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Governs which query results the secondary thread caches, and for how long.
+/// Kinds of query without a time to live are not cached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachePolicy {
+    ttls: HashMap<QueryKind, Duration>,
+}
+
+impl Default for CachePolicy {
+    /// Cache the roles, sites and platforms for five minutes
+    fn default() -> Self {
+        let ttl = Duration::from_secs(300);
+        Self::disabled()
+            .with_ttl(QueryKind::Roles, ttl)
+            .with_ttl(QueryKind::Sites, ttl)
+            .with_ttl(QueryKind::Platforms, ttl)
+    }
+}
+
+impl CachePolicy {
+    /// New up a CachePolicy which caches nothing
+    pub fn disabled() -> Self {
+        Self {
+            ttls: HashMap::new(),
+        }
+    }
+
+    /// Cache the results of `kind` of query for `ttl`
+    pub fn with_ttl(mut self, kind: QueryKind, ttl: Duration) -> Self {
+        self.ttls.insert(kind, ttl);
+        self
+    }
+
+    /// Stop caching the results of `kind` of query
+    pub fn without(mut self, kind: QueryKind) -> Self {
+        self.ttls.remove(&kind);
+        self
+    }
+
+    /// The time to live of `kind` of query, if it is cached
+    pub fn ttl(&self, kind: QueryKind) -> Option<Duration> {
+        self.ttls.get(&kind).cloned()
+    }
+}
+
+/// The query results cached by the secondary thread, along with when each expires
+pub(crate) struct Cache {
+    policy: CachePolicy,
+    entries: HashMap<Query, (Instant, Vec<String>)>,
+}
+
+impl Cache {
+    pub(crate) fn new(policy: CachePolicy) -> Self {
+        Self {
+            policy,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Retrieve the cached result of `query`, unless it is missing or has expired
+    pub(crate) fn get(&mut self, query: &Query) -> Option<Vec<String>> {
+        if self.policy.ttl(query.kind()).is_none() {
+            return None;
+        }
+        match self.entries.get(query) {
+            Some((expires, result)) if Instant::now() < *expires => {
+                log::debug!("Cache hit for {:?}", query);
+                return Some(result.clone());
+            }
+            Some(_) => log::debug!("Cache miss for {:?}, expired", query),
+            None => log::debug!("Cache miss for {:?}", query),
+        }
+        self.entries.remove(query);
+        None
+    }
+
+    /// Cache the result of `query`, if its kind is cached
+    pub(crate) fn put(&mut self, query: Query, result: &[String]) {
+        if let Some(ttl) = self.policy.ttl(query.kind()) {
+            self.entries
+                .insert(query, (Instant::now() + ttl, result.to_vec()));
+        }
+    }
+
+    /// Discard the cached results of `kind` of query
+    pub(crate) fn invalidate(&mut self, kind: QueryKind) {
+        let before = self.entries.len();
+        self.entries.retain(|query, _| query.kind() != kind);
+        log::debug!(
+            "Invalidated {} cached {} result(s)",
+            before - self.entries.len(),
+            kind
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sites() -> Vec<String> {
+        vec!["portland".to_string()]
+    }
+
+    #[test]
+    fn only_kinds_with_a_ttl_are_cached() {
+        let mut cache = Cache::new(CachePolicy::default());
+        cache.put(Query::Sites, &sites());
+        cache.put(Query::Packages, &sites());
+        assert_eq!(cache.get(&Query::Sites), Some(sites()));
+        assert_eq!(cache.get(&Query::Packages), None);
+    }
+
+    #[test]
+    fn cached_results_expire() {
+        let policy = CachePolicy::disabled().with_ttl(QueryKind::Sites, Duration::from_millis(10));
+        let mut cache = Cache::new(policy);
+        cache.put(Query::Sites, &sites());
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(cache.get(&Query::Sites), None);
+    }
+
+    #[test]
+    fn cached_results_can_be_invalidated_by_kind() {
+        let policy = CachePolicy::default().with_ttl(QueryKind::Levels, Duration::from_secs(60));
+        let mut cache = Cache::new(policy);
+        cache.put(Query::Levels("dev01".to_string()), &sites());
+        cache.put(Query::Levels("dev02".to_string()), &sites());
+        cache.put(Query::Sites, &sites());
+        cache.invalidate(QueryKind::Levels);
+        assert_eq!(cache.get(&Query::Levels("dev01".to_string())), None);
+        assert_eq!(cache.get(&Query::Levels("dev02".to_string())), None);
+        assert_eq!(cache.get(&Query::Sites), Some(sites()));
+    }
+}
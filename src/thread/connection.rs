@@ -0,0 +1,330 @@
+use super::*;
+use crate::backend::{Connector, HealthCheck};
+use crossbeam_channel::RecvTimeoutError;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Governs how the secondary thread reconnects to the database, and what happens
+/// to requests which arrive while it is disconnected.
+///
+/// Connection attempts are retried indefinitely. The delay between attempts starts
+/// at `initial_delay` and is multiplied by `multiplier` after each failure, up to
+/// `max_delay`. Requests received while disconnected are queued and handled, in
+/// order, once the connection is re-established. If more than `max_queued` requests
+/// are waiting, the oldest is failed with an error, reported to its family.
+/// Should the thread be told to quit in the meantime, the queued requests are
+/// dropped, or failed with an error if the ShutdownPolicy is to drain them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: u32,
+    pub max_queued: usize,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            multiplier: 2,
+            max_queued: 64,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// The delay before retrying, given the number of failed attempts so far
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .checked_pow(attempt.saturating_sub(1))
+            .unwrap_or(u32::max_value());
+        self.initial_delay
+            .checked_mul(factor)
+            .map(|delay| std::cmp::min(delay, self.max_delay))
+            .unwrap_or(self.max_delay)
+    }
+}
+
+/// Owns the backend on behalf of the secondary thread, re-establishing it
+/// when it is lost.
+///
+/// Health checks run against a second `control` connection, opened the first
+/// time one is requested.
+pub(crate) struct DbConnection<C: Connector> {
+    connector: C,
+    policy: ReconnectPolicy,
+    shutdown: ShutdownPolicy,
+    db: Option<C::Backend>,
+    cancel: Option<Arc<C::Cancel>>,
+    control: Option<C::Health>,
+}
+
+impl<C: Connector> DbConnection<C> {
+    pub(crate) fn new(connector: C, policy: ReconnectPolicy, shutdown: ShutdownPolicy) -> Self {
+        Self {
+            connector,
+            policy,
+            shutdown,
+            db: None,
+            cancel: None,
+            control: None,
+        }
+    }
+
+    pub(crate) fn is_connected(&self) -> bool {
+        self.db.is_some()
+    }
+
+    /// Retrieve the backend. Only call this once connected.
+    pub(crate) fn db(&mut self) -> &mut C::Backend {
+        self.db.as_mut().expect("not connected to database")
+    }
+
+    /// Retrieve the handle which cancels queries made of the backend, if connected
+    pub(crate) fn cancel_handle(&self) -> Option<Arc<C::Cancel>> {
+        self.cancel.clone()
+    }
+
+    // retrieve the control connection, opening it if need be
+    fn control(&mut self) -> Result<&mut C::Health, BackendError> {
+        if self.control.is_none() {
+            self.control = Some(self.connector.connect_health()?);
+        }
+        Ok(self
+            .control
+            .as_mut()
+            .expect("control connection opened above"))
+    }
+
+    /// Connect to the database, backing off between failed attempts. Requests
+    /// which arrive in the meantime are added to `pending`.
+    ///
+    /// # Returns
+    /// * true once connected
+    /// * false if OMsg::Quit was received, or the ui hung up, while waiting to retry.
+    ///   Under ShutdownPolicy::Drain, each request left in `pending` has been
+    ///   answered with an error.
+    pub(crate) fn connect(
+        &mut self,
+        receiver: &Receiver<Request>,
+        pending: &mut VecDeque<Request>,
+        conductor: &mut impl Notifier,
+        mailbox: &Mailbox,
+    ) -> bool {
+        let mut attempt = 0;
+        loop {
+            notify(IConnection::Connecting, None, conductor, mailbox);
+            match self.connector.connect() {
+                Ok((db, cancel)) => {
+                    log::info!("Connected to database {}", self.connector.describe());
+                    self.db = Some(db);
+                    self.cancel = Some(Arc::new(cancel));
+                    notify(IConnection::Connected, None, conductor, mailbox);
+                    return true;
+                }
+                Err(err) => {
+                    attempt += 1;
+                    let delay = self.policy.delay(attempt);
+                    log::warn!(
+                        "Unable to connect to database (attempt {}). Retrying in {:?}: {}",
+                        attempt,
+                        delay,
+                        err
+                    );
+                    notify(
+                        IConnection::Retrying {
+                            attempt,
+                            delay,
+                            error: err.to_string(),
+                        },
+                        None,
+                        conductor,
+                        mailbox,
+                    );
+                    if !self.wait(delay, receiver, pending, conductor, mailbox) {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drop the current connection after it has been found to be broken
+    pub(crate) fn disconnected(
+        &mut self,
+        reason: String,
+        conductor: &mut impl Notifier,
+        mailbox: &Mailbox,
+    ) {
+        log::error!("Lost connection to database: {}", reason);
+        self.db = None;
+        self.cancel = None;
+        self.control = None;
+        notify(IConnection::Disconnected(reason), None, conductor, mailbox);
+    }
+
+    /// Drop the current connection, if any, so that the next is made afresh.
+    /// Used after the secondary thread has panicked, as the connection may have
+    /// been left mid-query.
+    pub(crate) fn reset(&mut self) {
+        self.db = None;
+        self.cancel = None;
+        self.control = None;
+    }
+
+    // wait out the delay, queueing any requests which arrive
+    fn wait(
+        &self,
+        delay: Duration,
+        receiver: &Receiver<Request>,
+        pending: &mut VecDeque<Request>,
+        conductor: &mut impl Notifier,
+        mailbox: &Mailbox,
+    ) -> bool {
+        let deadline = Instant::now() + delay;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+            match receiver.recv_timeout(deadline - now) {
+                Err(RecvTimeoutError::Disconnected) => return false,
+                Ok(request) if request.msg == OMsg::Quit => {
+                    // the requests cannot be answered without a connection, but
+                    // those the application is waiting upon are told as much
+                    if self.shutdown == ShutdownPolicy::Drain {
+                        for abandoned in pending.drain(..) {
+                            let reason = format!(
+                                "Abandoned request {:?} on quitting while disconnected from db",
+                                abandoned.msg
+                            );
+                            fail(&abandoned, reason, conductor, mailbox);
+                        }
+                    }
+                    return false;
+                }
+                Ok(request) => self.queue(request, pending, conductor, mailbox),
+                Err(RecvTimeoutError::Timeout) => return true,
+            }
+        }
+    }
+
+    fn queue(
+        &self,
+        request: Request,
+        pending: &mut VecDeque<Request>,
+        conductor: &mut impl Notifier,
+        mailbox: &Mailbox,
+    ) {
+        if pending.len() >= self.policy.max_queued {
+            if let Some(dropped) = pending.pop_front() {
+                let reason = format!(
+                    "Dropped request {:?} while disconnected from db",
+                    dropped.msg
+                );
+                fail(&dropped, reason, conductor, mailbox);
+            }
+        }
+        pending.push_back(request);
+    }
+}
+
+// answer `request`, which will not be handled, with a connection error. It is
+// addressed, so that the error reaches the instance it was made for.
+fn fail(request: &Request, reason: String, conductor: &mut impl Notifier, mailbox: &Mailbox) {
+    mailbox.address(request);
+    report(
+        IError::new(request.msg.family(), ErrorCategory::Connection, reason)
+            .with_request(request.id),
+        conductor,
+        mailbox,
+    );
+    mailbox.release(request.id);
+}
+
+/// perform a submatch against the OConnection msg
+pub(crate) fn match_connection<C: Connector>(
+    msg: OConnection,
+    request: RequestId,
+    connection: &mut DbConnection<C>,
+    conductor: &mut impl Notifier,
+    mailbox: &Mailbox,
+) -> Outcome {
+    let result = connection
+        .control()
+        .and_then(|control| health_check(&msg, control));
+    match result {
+        Ok(reply) => notify(reply, Some(request), conductor, mailbox),
+        Err(err) => {
+            // problems with the control connection do not affect the backend,
+            // so we report them rather than reconnecting
+            connection.control = None;
+            report(
+                IError::from_backend(
+                    OConnection::FAMILY,
+                    &format!("Unable to perform {:?} health check", msg),
+                    &err,
+                )
+                .with_request(request),
+                conductor,
+                mailbox,
+            );
+        }
+    }
+    Outcome::Handled
+}
+
+fn health_check<H: HealthCheck>(
+    msg: &OConnection,
+    control: &mut H,
+) -> Result<IConnection, BackendError> {
+    match msg {
+        OConnection::Ping => Ok(IConnection::Pong(control.ping()?)),
+        OConnection::GetServerInfo => Ok(IConnection::ServerInfo(control.server_info()?)),
+        OConnection::GetCurrentUser => Ok(IConnection::CurrentUser(control.current_user()?)),
+    }
+}
+
+/// Inform the ui of a change in the state of the connection, or answer the
+/// health check `request`
+fn notify(
+    state: IConnection,
+    request: Option<RequestId>,
+    conductor: &mut impl Notifier,
+    mailbox: &Mailbox,
+) {
+    let event = match &state {
+        IConnection::Connecting => Connection::Connecting,
+        IConnection::Connected => Connection::Connected,
+        IConnection::Disconnected(_) => Connection::Disconnected,
+        IConnection::Retrying { .. } => Connection::Retrying,
+        IConnection::Pong(_) => Connection::Ping,
+        IConnection::ServerInfo(_) => Connection::GetServerInfo,
+        IConnection::CurrentUser(_) => Connection::GetCurrentUser,
+    };
+    deliver(
+        state.to_imsg(),
+        event.to_event(),
+        request,
+        conductor,
+        mailbox,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_backs_off_exponentially_up_to_max() {
+        let policy = ReconnectPolicy::default();
+        assert_eq!(policy.delay(1), Duration::from_millis(500));
+        assert_eq!(policy.delay(2), Duration::from_secs(1));
+        assert_eq!(policy.delay(4), Duration::from_secs(4));
+        assert_eq!(policy.delay(7), Duration::from_secs(30));
+        assert_eq!(policy.delay(100), Duration::from_secs(30));
+    }
+}
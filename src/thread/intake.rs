@@ -0,0 +1,354 @@
+//! The intake sits between the application and the secondary thread. It forwards
+//! each Request, save for OMsg::Cancel, which it acts upon immediately, as the
+//! secondary thread may be busy running the very query to be cancelled. Likewise,
+//! OMsg::Quit abandons the outstanding requests as soon as it arrives, unless the
+//! ShutdownPolicy is to drain them. Each request is tracked in the Mailbox as it
+//! arrives, so that the replies to those superseded since may be told apart.
+use super::*;
+use crate::{backend::CancelQuery, outgoing::RequestId};
+use crossbeam_channel::Sender;
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Why a running request was stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stop {
+    /// At the application's request, via OMsg::Cancel
+    Cancelled,
+    /// By the watchdog, for exceeding its timeout
+    TimedOut,
+}
+
+struct InFlightState<K> {
+    // the request being handled, along with the handle cancelling its queries
+    running: Option<(RequestId, Option<Arc<K>>)>,
+    // requests cancelled before they started, or while they could not be
+    // cancelled, so should they be retried
+    cancelled: HashSet<RequestId>,
+    // set once told to quit without draining. no further requests are started.
+    abandoned: bool,
+    // the running request, if it has been stopped, and why
+    stopped: Option<(RequestId, Stop)>,
+}
+
+/// Tracks the request the secondary thread is handling, so that the intake may
+/// cancel it. Shared between the two threads.
+pub(crate) struct InFlight<K> {
+    state: Mutex<InFlightState<K>>,
+}
+
+impl<K: CancelQuery> InFlight<K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: Mutex::new(InFlightState {
+                running: None,
+                cancelled: HashSet::new(),
+                abandoned: false,
+                stopped: None,
+            }),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<InFlightState<K>> {
+        self.state.lock().expect("InFlight mutex poisoned")
+    }
+
+    /// Record that the secondary thread is about to handle the request `id`
+    ///
+    /// # Arguments
+    /// * `id` - The id of the request
+    /// * `cancel` - Cancels the queries made on behalf of the request
+    ///
+    /// # Returns
+    /// * false if the request has been cancelled already, or the outstanding
+    ///   requests abandoned, and should be dropped
+    pub(crate) fn start(&self, id: RequestId, cancel: Option<Arc<K>>) -> bool {
+        let mut state = self.state();
+        if state.cancelled.remove(&id) || state.abandoned {
+            return false;
+        }
+        state.running = Some((id, cancel));
+        true
+    }
+
+    /// Record that the secondary thread has finished handling the request `id`
+    ///
+    /// # Returns
+    /// * Why the request was stopped, if it was
+    pub(crate) fn finish(&self, id: RequestId) -> Option<Stop> {
+        let mut state = self.state();
+        if let Some((running, _)) = state.running {
+            if running == id {
+                state.running = None;
+            }
+        }
+        match state.stopped {
+            Some((stopped, stop)) if stopped == id => {
+                state.stopped = None;
+                Some(stop)
+            }
+            _ => None,
+        }
+    }
+
+    /// Forget the cancellations of requests which have not started, provided
+    /// that `idle` holds, ie no request is waiting to be handled. Those
+    /// cancellations are of requests which have finished already, or were never
+    /// made. The intake forwards each request before acting upon its
+    /// cancellation, and `idle` is checked while holding the lock, so a request
+    /// on its way to the secondary thread is not affected.
+    pub(crate) fn forget_cancelled<F: FnOnce() -> bool>(&self, idle: F) {
+        let mut state = self.state();
+        if !state.cancelled.is_empty() && idle() {
+            log::debug!(
+                "Forgetting {} cancellation(s) of finished requests",
+                state.cancelled.len()
+            );
+            state.cancelled.clear();
+        }
+    }
+
+    /// Cancel the request `id`, if it is still running, as it has exceeded its
+    /// timeout
+    pub(crate) fn time_out(&self, id: RequestId) {
+        let cancel = {
+            let mut state = self.state();
+            let cancel = match &state.running {
+                Some((running, Some(cancel))) if *running == id => cancel.clone(),
+                _ => return,
+            };
+            state.stopped = Some((id, Stop::TimedOut));
+            cancel
+        };
+        log::warn!("Request {} timed out. Cancelling", id);
+        if let Err(err) = cancel.cancel() {
+            log::error!("Unable to cancel request {}: {}", id, err);
+        }
+    }
+
+    /// Cancel the request `id`. If it is running, its query is aborted. Otherwise,
+    /// it is dropped when the secondary thread gets to it. A request running
+    /// without the means to cancel it, eg while connecting, runs to completion,
+    /// but is dropped should it be retried.
+    pub(crate) fn cancel(&self, id: RequestId) {
+        let cancel = {
+            let mut state = self.state();
+            match &state.running {
+                Some((running, Some(cancel))) if *running == id => {
+                    let cancel = cancel.clone();
+                    state.stopped = Some((id, Stop::Cancelled));
+                    cancel
+                }
+                Some((running, None)) if *running == id => {
+                    log::warn!("Request {} cannot be cancelled while connecting", id);
+                    state.cancelled.insert(id);
+                    state.stopped = Some((id, Stop::Cancelled));
+                    return;
+                }
+                _ => {
+                    state.cancelled.insert(id);
+                    return;
+                }
+            }
+        };
+        // cancelling talks to the server, so we do it without holding the lock
+        log::info!("Cancelling running request {}", id);
+        if let Err(err) = cancel.cancel() {
+            log::error!("Unable to cancel request {}: {}", id, err);
+        }
+    }
+
+    /// Abandon the outstanding requests. The running query is cancelled, and no
+    /// further requests are started.
+    pub(crate) fn abandon(&self) {
+        let running = {
+            let mut state = self.state();
+            state.abandoned = true;
+            state.running.as_ref().map(|(id, _)| *id)
+        };
+        if let Some(id) = running {
+            self.cancel(id);
+        }
+    }
+
+    /// Whether the outstanding requests have been abandoned
+    pub(crate) fn is_abandoned(&self) -> bool {
+        self.state().abandoned
+    }
+}
+
+/// Spawn the intake thread. It runs until OMsg::Quit is forwarded, the
+/// application hangs up, or the secondary thread has stopped.
+///
+/// # Arguments
+/// * `receiver` - Receives Requests from the application
+/// * `worker` - Forwards Requests to the secondary thread
+/// * `in_flight` - Tracks the request being handled by the secondary thread
+/// * `policy` - Whether OMsg::Quit abandons the outstanding requests
+/// * `mailbox` - Tracks the latest request of each kind
+pub(crate) fn spawn_intake<K>(
+    receiver: Receiver<Request>,
+    worker: Sender<Request>,
+    in_flight: Arc<InFlight<K>>,
+    policy: ShutdownPolicy,
+    mailbox: Mailbox,
+) -> JoinHandle<()>
+where
+    K: CancelQuery + Send + Sync + 'static,
+{
+    std::thread::spawn(move || {
+        for request in receiver.iter() {
+            mailbox.track(&request);
+            match request.msg {
+                OMsg::Cancel(id) => in_flight.cancel(id),
+                _ => {
+                    let quit = request.msg == OMsg::Quit;
+                    if quit && policy == ShutdownPolicy::Abandon {
+                        log::info!("Quitting. Abandoning outstanding requests");
+                        in_flight.abandon();
+                    }
+                    if worker.send(request).is_err() || quit {
+                        break;
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Drop each queued request which is superseded by a later request of the same
+/// kind, made on behalf of the same widget instance. Requests for different
+/// instances are each answered. The order of the remaining requests is preserved.
+pub(crate) fn supersede(pending: &mut VecDeque<Request>) {
+    let mut kinds = HashSet::new();
+    let mut kept = VecDeque::with_capacity(pending.len());
+    while let Some(request) = pending.pop_back() {
+        if request.msg.is_supersedable() && !kinds.insert((request.msg.kind(), request.instance)) {
+            log::info!(
+                "Dropping request {} ({:?}), superseded by a later request",
+                request.id,
+                request.msg
+            );
+            continue;
+        }
+        kept.push_front(request);
+    }
+    *pending = kept;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backend::MemoryBackend, InstanceId, ToOMsg};
+
+    #[test]
+    fn later_requests_supersede_queued_requests_of_same_kind() {
+        let mut pending = VecDeque::new();
+        pending.push_back(OVpinDialog::GetLevels("dev01".to_string()).to_request());
+        pending.push_back(OVpinDialog::GetRoles.to_request());
+        pending.push_back(OVpinDialog::GetLevels("dev02".to_string()).to_request());
+        pending.push_back(OMsg::Quit.into());
+        pending.push_back(OMsg::Quit.into());
+        supersede(&mut pending);
+        let msgs = pending.into_iter().map(|r| r.msg).collect::<Vec<_>>();
+        assert_eq!(
+            msgs,
+            vec![
+                OMsg::VpinDialog(OVpinDialog::GetRoles),
+                OMsg::VpinDialog(OVpinDialog::GetLevels("dev02".to_string())),
+                OMsg::Quit,
+                OMsg::Quit,
+            ]
+        );
+    }
+
+    #[test]
+    fn requests_for_other_instances_are_not_superseded() {
+        let mut pending = VecDeque::new();
+        pending.push_back(OVpinDialog::GetRoles.to_request_for(InstanceId::from(1)));
+        pending.push_back(OVpinDialog::GetRoles.to_request_for(InstanceId::from(2)));
+        pending.push_back(OVpinDialog::GetRoles.to_request());
+        pending.push_back(OVpinDialog::GetRoles.to_request_for(InstanceId::from(1)));
+        supersede(&mut pending);
+        let instances = pending.into_iter().map(|r| r.instance).collect::<Vec<_>>();
+        assert_eq!(
+            instances,
+            vec![Some(InstanceId::from(2)), None, Some(InstanceId::from(1))]
+        );
+    }
+
+    #[test]
+    fn queued_requests_can_be_cancelled() {
+        let in_flight = InFlight::<MemoryBackend>::new();
+        let id = RequestId::from(1);
+        in_flight.cancel(id);
+        assert!(!in_flight.start(id, None));
+        // the cancellation is spent
+        assert!(in_flight.start(id, None));
+    }
+
+    #[test]
+    fn abandoning_cancels_the_running_request_and_drops_the_rest() {
+        let backend = MemoryBackend::new();
+        let in_flight = InFlight::new();
+        assert!(in_flight.start(RequestId::from(1), Some(Arc::new(backend.clone()))));
+        in_flight.abandon();
+        assert!(in_flight.is_abandoned());
+        assert_eq!(backend.queries(), vec!["cancel"]);
+        in_flight.finish(RequestId::from(1));
+        assert!(!in_flight.start(RequestId::from(2), None));
+    }
+
+    #[test]
+    fn running_requests_can_be_timed_out() {
+        let backend = MemoryBackend::new();
+        let in_flight = InFlight::new();
+        let id = RequestId::from(1);
+        assert!(in_flight.start(id, Some(Arc::new(backend.clone()))));
+        // a request which has finished is unaffected
+        in_flight.time_out(RequestId::from(2));
+        assert!(backend.queries().is_empty());
+        in_flight.time_out(id);
+        assert_eq!(backend.queries(), vec!["cancel"]);
+        assert_eq!(in_flight.finish(id), Some(Stop::TimedOut));
+    }
+
+    #[test]
+    fn running_requests_can_be_cancelled() {
+        let backend = MemoryBackend::new();
+        let in_flight = InFlight::new();
+        let id = RequestId::from(1);
+        assert!(in_flight.start(id, Some(Arc::new(backend.clone()))));
+        in_flight.cancel(id);
+        assert_eq!(backend.queries(), vec!["cancel"]);
+        assert_eq!(in_flight.finish(id), Some(Stop::Cancelled));
+        // once finished, a cancel applies to the next run of the request, if any
+        in_flight.cancel(id);
+        assert_eq!(backend.queries(), vec!["cancel"]);
+    }
+
+    #[test]
+    fn requests_cancelled_while_connecting_are_not_retried() {
+        let in_flight = InFlight::<MemoryBackend>::new();
+        let id = RequestId::from(1);
+        assert!(in_flight.start(id, None));
+        in_flight.cancel(id);
+        assert_eq!(in_flight.finish(id), Some(Stop::Cancelled));
+        assert!(!in_flight.start(id, None));
+    }
+
+    #[test]
+    fn cancellations_of_finished_requests_are_forgotten_when_idle() {
+        let in_flight = InFlight::<MemoryBackend>::new();
+        let (finished, queued) = (RequestId::from(1), RequestId::from(2));
+        assert!(in_flight.start(finished, None));
+        in_flight.finish(finished);
+        in_flight.cancel(finished);
+        in_flight.cancel(queued);
+        // a request is waiting, which may be the one cancelled
+        in_flight.forget_cancelled(|| false);
+        assert_eq!(in_flight.state().cancelled.len(), 2);
+        in_flight.forget_cancelled(|| true);
+        assert!(in_flight.state().cancelled.is_empty());
+    }
+}
@@ -1,95 +1,104 @@
 use super::*;
 
-pub(crate) fn match_main_toolbar(
+/// perform a submatch against the OMainToolbar msg
+pub(crate) fn match_main_toolbar<B: PackratBackend, N: Notifier>(
     msg: OMainToolbar,
-    db: &mut packybara::db::packrat::PackratDb,
-    conductor: &mut qt_thread_conductor::conductor::Conductor<Event>,
-    sender: &Sender<IMsg>,
-) {
+    request: RequestId,
+    db: &mut B,
+    conductor: &mut N,
+    mailbox: &Mailbox,
+) -> Outcome {
     match msg {
         OMainToolbar::GetShows => {
-            let shows = match db.find_all_levels().depth(1).query() {
+            let shows = match db.shows() {
                 Ok(shows) => shows,
                 Err(err) => {
-                    sender
-                        .send(IMsg::Error(format!("Unable to get shows from db: {}", err)))
-                        .expect("unable to send error msg");
-                    conductor.signal(Event::Error);
-                    return;
+                    return query_failed(
+                        "Unable to get shows from db",
+                        err,
+                        OMainToolbar::FAMILY,
+                        request,
+                        conductor,
+                        mailbox,
+                    );
                 }
             };
             let mut results = vec!["facility".to_string()];
-            shows
-                .into_iter()
-                .map(|mut show| std::mem::replace(&mut show.show, String::new()))
-                .for_each(|show| results.push(show));
-            sender
-                .send(IMainToolbar::Shows(results).to_imsg())
-                .expect("unable to send shows");
-            conductor.signal(MainToolbar::GetShows.to_event());
+            results.extend(shows);
+            deliver(
+                IMainToolbar::Shows(results).to_imsg(),
+                MainToolbar::GetShows.to_event(),
+                Some(request),
+                conductor,
+                mailbox,
+            );
         }
         OMainToolbar::GetRoles => {
-            let roles = match db.find_all_roles().query() {
+            let roles = match db.roles() {
                 Ok(roles) => roles,
                 Err(err) => {
-                    sender
-                        .send(IMsg::Error(format!("Unable to get roles from db: {}", err)))
-                        .expect("unable to send error msg");
-                    conductor.signal(Event::Error);
-                    return;
+                    return query_failed(
+                        "Unable to get roles from db",
+                        err,
+                        OMainToolbar::FAMILY,
+                        request,
+                        conductor,
+                        mailbox,
+                    );
                 }
             };
-            let roles = roles
-                .into_iter()
-                .map(|mut role| std::mem::replace(&mut role.role, String::new()))
-                .collect::<Vec<_>>();
-            sender
-                .send(IMainToolbar::Roles(roles).to_imsg())
-                .expect("unable to send roles");
-            conductor.signal(MainToolbar::GetRoles.to_event());
+            deliver(
+                IMainToolbar::Roles(roles).to_imsg(),
+                MainToolbar::GetRoles.to_event(),
+                Some(request),
+                conductor,
+                mailbox,
+            );
         }
         OMainToolbar::GetPlatforms => {
-            let platforms = match db.find_all_platforms().query() {
+            let platforms = match db.platforms() {
                 Ok(platforms) => platforms,
                 Err(err) => {
-                    sender
-                        .send(IMsg::Error(format!(
-                            "Unable to get platforms from db: {}",
-                            err
-                        )))
-                        .expect("unable to send error msg");
-                    conductor.signal(Event::Error);
-                    return;
+                    return query_failed(
+                        "Unable to get platforms from db",
+                        err,
+                        OMainToolbar::FAMILY,
+                        request,
+                        conductor,
+                        mailbox,
+                    );
                 }
             };
-            let platforms = platforms
-                .into_iter()
-                .map(|mut platform| std::mem::replace(&mut platform.name, String::new()))
-                .collect::<Vec<_>>();
-            sender
-                .send(IMainToolbar::Platforms(platforms).to_imsg())
-                .expect("unable to send platforms");
-            conductor.signal(MainToolbar::GetPlatforms.to_event());
+            deliver(
+                IMainToolbar::Platforms(platforms).to_imsg(),
+                MainToolbar::GetPlatforms.to_event(),
+                Some(request),
+                conductor,
+                mailbox,
+            );
         }
         OMainToolbar::GetSites => {
-            let sites = match db.find_all_sites().query() {
+            let sites = match db.sites() {
                 Ok(sites) => sites,
                 Err(err) => {
-                    sender
-                        .send(IMsg::Error(format!("Unable to get sites from db: {}", err)))
-                        .expect("unable to send error msg");
-                    conductor.signal(Event::Error);
-                    return;
+                    return query_failed(
+                        "Unable to get sites from db",
+                        err,
+                        OMainToolbar::FAMILY,
+                        request,
+                        conductor,
+                        mailbox,
+                    );
                 }
             };
-            let sites = sites
-                .into_iter()
-                .map(|mut site| std::mem::replace(&mut site.name, String::new()))
-                .collect::<Vec<_>>();
-            sender
-                .send(IMainToolbar::Sites(sites).to_imsg())
-                .expect("unable to send sites");
-            conductor.signal(MainToolbar::GetSites.to_event());
+            deliver(
+                IMainToolbar::Sites(sites).to_imsg(),
+                MainToolbar::GetSites.to_event(),
+                Some(request),
+                conductor,
+                mailbox,
+            );
         }
     }
+    Outcome::Handled
 }
@@ -1,35 +1,36 @@
 use super::*;
 
-/// perform a submatch against the OVpinDialog msg
-pub(crate) fn match_package_withs(
+/// perform a submatch against the OPackageWiths msg
+pub(crate) fn match_package_withs<B: PackratBackend, N: Notifier>(
     msg: OPackageWiths,
-    db: &mut packybara::db::packrat::PackratDb,
-    conductor: &mut qt_thread_conductor::conductor::Conductor<Event>,
-    sender: &Sender<IMsg>,
-) {
+    request: RequestId,
+    db: &mut B,
+    conductor: &mut N,
+    mailbox: &Mailbox,
+) -> Outcome {
     match msg {
         OPackageWiths::GetPackages => {
-            let packages = match db.find_all_packages().query() {
+            let packages = match db.packages() {
                 Ok(packages) => packages,
                 Err(err) => {
-                    sender
-                        .send(IMsg::Error(format!(
-                            "Unable to get packages from db: {}",
-                            err
-                        )))
-                        .expect("unable to send error msg");
-                    conductor.signal(Event::Error);
-                    return;
+                    return query_failed(
+                        "Unable to get packages from db",
+                        err,
+                        OPackageWiths::FAMILY,
+                        request,
+                        conductor,
+                        mailbox,
+                    );
                 }
             };
-            let packages = packages
-                .into_iter()
-                .map(|mut x| std::mem::replace(&mut x.name, String::new()))
-                .collect::<Vec<_>>();
-            sender
-                .send(IPackageWiths::Packages(packages).to_imsg())
-                .expect("unable to send packages");
-            conductor.signal(PackageWiths::GetPackages.to_event());
+            deliver(
+                IPackageWiths::Packages(packages).to_imsg(),
+                PackageWiths::GetPackages.to_event(),
+                Some(request),
+                conductor,
+                mailbox,
+            );
         }
     }
+    Outcome::Handled
 }
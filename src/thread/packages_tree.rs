@@ -1,58 +1,78 @@
 use super::*;
 
-/// perform a submatch against the OVpinDialog msg
-pub(crate) fn match_packages_tree(
+/// perform a submatch against the OPackagesTree msg. The sites are sent along
+/// with `current_site`, which SetCurrentSite replaces.
+pub(crate) fn match_packages_tree<B: PackratBackend, N: Notifier>(
     msg: OPackagesTree,
-    db: &mut packybara::db::packrat::PackratDb,
-    conductor: &mut qt_thread_conductor::conductor::Conductor<Event>,
-    sender: &Sender<IMsg>,
-) {
+    request: RequestId,
+    current_site: &mut String,
+    db: &mut B,
+    conductor: &mut N,
+    mailbox: &Mailbox,
+) -> Outcome {
     match msg {
         OPackagesTree::GetPackages => {
-            let packages = match db.find_all_packages().query() {
+            let packages = match db.packages() {
                 Ok(packages) => packages,
                 Err(err) => {
-                    sender
-                        .send(IMsg::Error(format!(
-                            "Unable to get packages from db: {}",
-                            err
-                        )))
-                        .expect("unable to send error msg");
-                    conductor.signal(Event::Error);
-                    return;
+                    return query_failed(
+                        "Unable to get packages from db",
+                        err,
+                        OPackagesTree::FAMILY,
+                        request,
+                        conductor,
+                        mailbox,
+                    );
                 }
             };
-            let packages = packages
-                .into_iter()
-                .map(|mut x| std::mem::replace(&mut x.name, String::new()))
-                .collect::<Vec<_>>();
-            sender
-                .send(IPackagesTree::Packages(packages).to_imsg())
-                .expect("unable to send packages");
-            conductor.signal(PackagesTree::GetPackages.to_event());
+            deliver(
+                IPackagesTree::Packages(packages).to_imsg(),
+                PackagesTree::GetPackages.to_event(),
+                Some(request),
+                conductor,
+                mailbox,
+            );
+        }
+
+        OPackagesTree::SetCurrentSite(site) => {
+            log::info!("Running at site {} rather than {}", site, current_site);
+            *current_site = site;
+            return match_packages_tree(
+                OPackagesTree::GetSites,
+                request,
+                current_site,
+                db,
+                conductor,
+                mailbox,
+            );
         }
 
         OPackagesTree::GetSites => {
-            let sites = match db.find_all_sites().query() {
+            let sites = match db.sites() {
                 Ok(sites) => sites,
                 Err(e) => {
-                    sender
-                        .send(IMsg::Error(format!("Unable to get sites from db: {}", e)))
-                        .expect("unable to send error msg");
-                    conductor.signal(Event::Error);
-                    return;
+                    return query_failed(
+                        "Unable to get sites from db",
+                        e,
+                        OPackagesTree::FAMILY,
+                        request,
+                        conductor,
+                        mailbox,
+                    );
                 }
             };
-            // we use std::mem::replace because this should be a bit more efficient
-            // than clone, and certainly more
-            let sites = sites
-                .into_iter()
-                .map(|mut x| std::mem::replace(&mut x.name, String::new()))
-                .collect::<Vec<_>>();
-            sender
-                .send(IPackagesTree::Sites(sites).to_imsg())
-                .expect("unable to send sites");
-            conductor.signal(PackagesTree::GetSites.to_event());
+            deliver(
+                IPackagesTree::Sites {
+                    sites,
+                    current: current_site.clone(),
+                }
+                .to_imsg(),
+                PackagesTree::GetSites.to_event(),
+                Some(request),
+                conductor,
+                mailbox,
+            );
         }
     }
+    Outcome::Handled
 }
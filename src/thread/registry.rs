@@ -0,0 +1,240 @@
+//! The secondary thread hands each request to the handler registered for its
+//! family. The built-in families are registered by `Handlers::new`. Applications
+//! may register their own, whose requests are sent as OMsg::Custom, or replace a
+//! built-in family's handler.
+use super::*;
+use crate::CustomMsg;
+use std::collections::HashMap;
+
+/// Handles the requests of a family on the secondary thread. The handler answers
+/// via the Context, and returns the Outcome of handling the request.
+pub type Handler<C> = Box<dyn FnMut(OMsg, &mut Context<'_, C>) -> Outcome + Send>;
+
+/// What a Handler is given to answer a request with
+pub struct Context<'a, C: Connector> {
+    pub(crate) request: RequestId,
+    pub(crate) connection: &'a mut DbConnection<C>,
+    pub(crate) results: &'a mut Results,
+    pub(crate) cache: &'a mut Cache,
+    pub(crate) stats: &'a mut Stats,
+    pub(crate) current_site: &'a mut String,
+    pub(crate) invalidated: &'a mut Vec<QueryKind>,
+    pub(crate) conductor: &'a mut dyn Notifier,
+    pub(crate) mailbox: &'a Mailbox,
+}
+
+impl<'a, C: Connector> Context<'a, C> {
+    /// The id of the request being handled
+    pub fn request(&self) -> RequestId {
+        self.request
+    }
+
+    /// The site the application is running at. See `SitePolicy`.
+    pub fn current_site(&self) -> &str {
+        self.current_site
+    }
+
+    /// Change the site the application is running at, as
+    /// PackagesTree::SetCurrentSite does. It is sent along with the sites in
+    /// the replies to subsequent requests.
+    pub fn set_current_site<S: Into<String>>(&mut self, site: S) {
+        *self.current_site = site.into();
+    }
+
+    /// Discard the cached results of `kind` of query once the request has been
+    /// handled, eg having written to the tables it reads
+    pub fn invalidate(&mut self, kind: QueryKind) {
+        self.invalidated.push(kind);
+    }
+
+    /// The backend, by way of the cache. Queries made of it are shared with the
+    /// rest of the batch, and recorded in the statistics.
+    pub fn db(&mut self) -> impl PackratBackend + '_ {
+        self.split().0
+    }
+
+    /// The backend itself, eg PackratDb, for queries PackratBackend does not
+    /// offer. Queries made of it bypass the cache and the statistics.
+    pub fn backend(&mut self) -> &mut C::Backend {
+        self.connection.db()
+    }
+
+    /// Answer the request with `msg`, signaled as `msg.event()`
+    pub fn reply(&mut self, msg: CustomMsg) {
+        let event = msg.event();
+        self.deliver(msg.to_imsg(), event);
+    }
+
+    /// Answer the request with `msg`, signaled as `event`, eg to answer the
+    /// request of a built-in family
+    pub fn deliver(&mut self, msg: IMsg, event: Event) {
+        deliver(
+            msg,
+            event,
+            Some(self.request),
+            &mut self.conductor,
+            self.mailbox,
+        );
+    }
+
+    /// Report that the request has failed
+    pub fn report(&mut self, error: IError) {
+        let error = error.with_request(self.request);
+        report(error, &mut self.conductor, self.mailbox);
+    }
+
+    /// Report a failed query, as `query_failed`
+    ///
+    /// # Arguments
+    /// * `description` - Describes the failed query, eg "Unable to get shots from db"
+    /// * `err` - The error returned by the query
+    /// * `family` - The family of the request which made the query
+    ///
+    /// # Returns
+    /// * The Outcome for the handler to return
+    pub fn query_failed(
+        &mut self,
+        description: &str,
+        err: BackendError,
+        family: &'static str,
+    ) -> Outcome {
+        query_failed(
+            description,
+            err,
+            family,
+            self.request,
+            &mut self.conductor,
+            self.mailbox,
+        )
+    }
+
+    /// Borrow the backend, as `db`, alongside the means of answering, eg to
+    /// hand them on to functions generic over the backend and the Notifier, as
+    /// the built-in handlers do
+    ///
+    /// # Returns
+    /// * The backend, by way of the cache
+    /// * The Notifier, which signals the application
+    /// * The Mailbox, to which the replies are posted
+    pub fn split(
+        &mut self,
+    ) -> (
+        impl PackratBackend + '_,
+        &mut (dyn Notifier + 'a),
+        &'a Mailbox,
+    ) {
+        let db = SharedQueries::new(self.connection.db(), self.results, self.cache, self.stats);
+        (db, &mut *self.conductor, self.mailbox)
+    }
+}
+
+/// The handlers of each family, keyed by family name
+pub struct Handlers<C: Connector> {
+    handlers: HashMap<&'static str, Handler<C>>,
+}
+
+impl<C: Connector + 'static> Default for Handlers<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Connector + 'static> Handlers<C> {
+    /// New up a Handlers with the built-in families registered
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+        .register(OVpinDialog::FAMILY, |msg, ctx| match msg {
+            OMsg::VpinDialog(msg) => {
+                let request = ctx.request();
+                let (mut db, mut conductor, mailbox) = ctx.split();
+                match_vpin_dialog(msg, request, &mut db, &mut conductor, mailbox)
+            }
+            msg => misrouted(msg),
+        })
+        .register(OPackagesTree::FAMILY, |msg, ctx| match msg {
+            OMsg::PackagesTree(msg) => {
+                let request = ctx.request();
+                let mut current_site = ctx.current_site().to_string();
+                let outcome = {
+                    let (mut db, mut conductor, mailbox) = ctx.split();
+                    match_packages_tree(
+                        msg,
+                        request,
+                        &mut current_site,
+                        &mut db,
+                        &mut conductor,
+                        mailbox,
+                    )
+                };
+                ctx.set_current_site(current_site);
+                outcome
+            }
+            msg => misrouted(msg),
+        })
+        .register(OPackageWiths::FAMILY, |msg, ctx| match msg {
+            OMsg::PackageWiths(msg) => {
+                let request = ctx.request();
+                let (mut db, mut conductor, mailbox) = ctx.split();
+                match_package_withs(msg, request, &mut db, &mut conductor, mailbox)
+            }
+            msg => misrouted(msg),
+        })
+        .register(OMainToolbar::FAMILY, |msg, ctx| match msg {
+            OMsg::MainToolbar(msg) => {
+                let request = ctx.request();
+                let (mut db, mut conductor, mailbox) = ctx.split();
+                match_main_toolbar(msg, request, &mut db, &mut conductor, mailbox)
+            }
+            msg => misrouted(msg),
+        })
+        .register(OConnection::FAMILY, |msg, ctx| match msg {
+            OMsg::Connection(msg) => match_connection(
+                msg,
+                ctx.request,
+                ctx.connection,
+                &mut ctx.conductor,
+                ctx.mailbox,
+            ),
+            msg => misrouted(msg),
+        })
+    }
+
+    /// Hand the requests of `family` to `handler`, replacing its handler if
+    /// it has one already
+    ///
+    /// # Arguments
+    /// * `family` - The name of the family, eg "Shots". Requests are routed by
+    ///              `OMsg::family`, so this matches `CustomMsg::family` of
+    ///              the family's requests.
+    /// * `handler` - Answers each request of the family
+    pub fn register<F>(mut self, family: &'static str, handler: F) -> Self
+    where
+        F: FnMut(OMsg, &mut Context<'_, C>) -> Outcome + Send + 'static,
+    {
+        self.handlers.insert(family, Box::new(handler));
+        self
+    }
+
+    /// Whether a handler is registered for `family`
+    pub fn contains(&self, family: &str) -> bool {
+        self.handlers.contains_key(family)
+    }
+
+    /// Hand `msg` to the handler of its family
+    ///
+    /// # Returns
+    /// * The Outcome of handling it, or None if its family has no handler
+    pub(crate) fn handle(&mut self, msg: OMsg, ctx: &mut Context<'_, C>) -> Option<Outcome> {
+        let handler = self.handlers.get_mut(msg.family())?;
+        Some(handler(msg, ctx))
+    }
+}
+
+// a request handed to the handler of another family. only possible should a
+// built-in family be registered under the wrong name.
+fn misrouted(msg: OMsg) -> Outcome {
+    log::error!("Request {:?} routed to the wrong handler. Dropping", msg);
+    Outcome::Handled
+}
@@ -0,0 +1,307 @@
+//! Several widgets ask for the same data, eg the sites, which are wanted by the
+//! vpin dialog, the packages tree and the main toolbar alike. Requests queued
+//! together which make the same query are handled as a batch, and the query is
+//! run once on behalf of the whole batch.
+use super::cache::Cache;
+use super::*;
+use crate::{incoming::Stats, outgoing::QueryKind};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Instant;
+
+/// The packrat query a request makes
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Query {
+    Roles,
+    Sites,
+    Platforms,
+    Packages,
+    Shows,
+    Levels(String),
+}
+
+impl Query {
+    /// The query made on behalf of `msg`, if any. Health checks and control
+    /// messages make none, so are never batched. Nor are custom requests, whose
+    /// queries are up to their handler.
+    pub(crate) fn of(msg: &OMsg) -> Option<Query> {
+        let query = match msg {
+            OMsg::VpinDialog(OVpinDialog::GetRoles) => Query::Roles,
+            OMsg::VpinDialog(OVpinDialog::GetSites) => Query::Sites,
+            OMsg::VpinDialog(OVpinDialog::GetLevels(show)) => Query::Levels(show.clone()),
+            OMsg::PackagesTree(OPackagesTree::GetPackages) => Query::Packages,
+            OMsg::PackagesTree(OPackagesTree::GetSites) => Query::Sites,
+            OMsg::PackagesTree(OPackagesTree::SetCurrentSite(_)) => Query::Sites,
+            OMsg::PackageWiths(OPackageWiths::GetPackages) => Query::Packages,
+            OMsg::MainToolbar(OMainToolbar::GetShows) => Query::Shows,
+            OMsg::MainToolbar(OMainToolbar::GetRoles) => Query::Roles,
+            OMsg::MainToolbar(OMainToolbar::GetPlatforms) => Query::Platforms,
+            OMsg::MainToolbar(OMainToolbar::GetSites) => Query::Sites,
+            OMsg::Connection(_)
+            | OMsg::Custom(_)
+            | OMsg::Cancel(_)
+            | OMsg::InvalidateCache(_)
+            | OMsg::GetStats
+            | OMsg::Quit => return None,
+        };
+        Some(query)
+    }
+
+    /// The kind of the query, by which its results are cached and invalidated
+    pub(crate) fn kind(&self) -> QueryKind {
+        match self {
+            Query::Roles => QueryKind::Roles,
+            Query::Sites => QueryKind::Sites,
+            Query::Platforms => QueryKind::Platforms,
+            Query::Packages => QueryKind::Packages,
+            Query::Shows => QueryKind::Shows,
+            Query::Levels(_) => QueryKind::Levels,
+        }
+    }
+}
+
+/// Remove the queued requests which make the same query as `request`, returning
+/// them after `request`, in the order they were queued. Requests queued after one
+/// which invalidates the query's results, or changes what is sent along with
+/// them, are left alone, as they are owed the fresh reply. So is the request
+/// which makes the change, eg PackagesTree::SetCurrentSite, which must not be
+/// handled ahead of the requests queued before it.
+pub(crate) fn gather(request: Request, pending: &mut VecDeque<Request>) -> Vec<Request> {
+    let query = match Query::of(&request.msg) {
+        Some(query) => query,
+        None => return vec![request],
+    };
+    let mut batch = vec![request];
+    let mut rest = VecDeque::with_capacity(pending.len());
+    let mut invalidated = false;
+    for queued in pending.drain(..) {
+        invalidated = invalidated
+            || queued.msg.invalidates().contains(&query.kind())
+            || changes_replies(&queued.msg, &query);
+        if !invalidated && Query::of(&queued.msg).as_ref() == Some(&query) {
+            batch.push(queued);
+        } else {
+            rest.push_back(queued);
+        }
+    }
+    *pending = rest;
+    if batch.len() > 1 {
+        log::debug!(
+            "Answering {} requests with a single {:?} query",
+            batch.len(),
+            query
+        );
+    }
+    batch
+}
+
+// whether handling `msg` changes the replies to requests making `query`, other
+// than by invalidating its results, as SetCurrentSite changes the current site
+// sent along with the sites
+fn changes_replies(msg: &OMsg, query: &Query) -> bool {
+    matches!(
+        (msg, query),
+        (
+            OMsg::PackagesTree(OPackagesTree::SetCurrentSite(_)),
+            Query::Sites
+        )
+    )
+}
+
+/// The results of the queries made on behalf of a batch
+pub(crate) type Results = HashMap<Query, Result<Vec<String>, SharedError>>;
+
+/// A query error shared by each request in a batch. BackendError cannot be
+/// cloned, so each request receives a SharedError wrapping the original, which
+/// remains reachable as its source.
+#[derive(Debug, Clone)]
+pub(crate) struct SharedError(Rc<BackendError>);
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for SharedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.0.as_ref().as_ref())
+    }
+}
+
+/// A PackratBackend which answers each query from `results` if it has been run
+/// on behalf of the batch already, or from the `cache`, and runs it against `db`
+/// otherwise. Queries run against `db` are timed, and recorded in `stats`.
+pub(crate) struct SharedQueries<'a, B> {
+    db: &'a mut B,
+    results: &'a mut Results,
+    cache: &'a mut Cache,
+    stats: &'a mut Stats,
+}
+
+impl<'a, B: PackratBackend> SharedQueries<'a, B> {
+    pub(crate) fn new(
+        db: &'a mut B,
+        results: &'a mut Results,
+        cache: &'a mut Cache,
+        stats: &'a mut Stats,
+    ) -> Self {
+        Self {
+            db,
+            results,
+            cache,
+            stats,
+        }
+    }
+
+    fn shared<F>(&mut self, query: Query, run: F) -> Result<Vec<String>, BackendError>
+    where
+        F: FnOnce(&mut B) -> Result<Vec<String>, BackendError>,
+    {
+        if let Some(result) = self.results.get(&query) {
+            return result.clone().map_err(BackendError::from);
+        }
+        if let Some(result) = self.cache.get(&query) {
+            self.results.insert(query, Ok(result.clone()));
+            return Ok(result);
+        }
+        let started = Instant::now();
+        let result = run(&mut *self.db).map_err(|err| SharedError(Rc::new(err)));
+        self.stats
+            .record(query.kind(), started.elapsed(), result.is_ok());
+        match &result {
+            Ok(result) => self.cache.put(query.clone(), result),
+            // a cancellation applies to a single request. the rest of the batch
+            // runs the query afresh.
+            Err(err) if is_cancellation(err) => return result.map_err(BackendError::from),
+            Err(_) => (),
+        }
+        self.results.insert(query, result.clone());
+        result.map_err(BackendError::from)
+    }
+}
+
+impl<'a, B: PackratBackend> PackratBackend for SharedQueries<'a, B> {
+    fn roles(&mut self) -> Result<Vec<String>, BackendError> {
+        self.shared(Query::Roles, |db| db.roles())
+    }
+
+    fn sites(&mut self) -> Result<Vec<String>, BackendError> {
+        self.shared(Query::Sites, |db| db.sites())
+    }
+
+    fn platforms(&mut self) -> Result<Vec<String>, BackendError> {
+        self.shared(Query::Platforms, |db| db.platforms())
+    }
+
+    fn packages(&mut self) -> Result<Vec<String>, BackendError> {
+        self.shared(Query::Packages, |db| db.packages())
+    }
+
+    fn shows(&mut self) -> Result<Vec<String>, BackendError> {
+        self.shared(Query::Shows, |db| db.shows())
+    }
+
+    fn levels(&mut self, show: &str) -> Result<Vec<String>, BackendError> {
+        self.shared(Query::Levels(show.to_string()), |db| db.levels(show))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backend::MemoryBackend, ToOMsg};
+
+    #[test]
+    fn requests_making_the_same_query_are_gathered() {
+        let mut pending = VecDeque::new();
+        pending.push_back(OMainToolbar::GetRoles.to_request());
+        pending.push_back(OMainToolbar::GetSites.to_request());
+        pending.push_back(OVpinDialog::GetLevels("dev01".to_string()).to_request());
+        pending.push_back(OPackagesTree::GetSites.to_request());
+        let batch = gather(OVpinDialog::GetSites.to_request(), &mut pending);
+        let msgs = batch.into_iter().map(|r| r.msg).collect::<Vec<_>>();
+        assert_eq!(
+            msgs,
+            vec![
+                OMsg::VpinDialog(OVpinDialog::GetSites),
+                OMsg::MainToolbar(OMainToolbar::GetSites),
+                OMsg::PackagesTree(OPackagesTree::GetSites),
+            ]
+        );
+        assert_eq!(pending.len(), 2);
+        // control messages are never batched
+        pending.push_back(OMsg::Quit.into());
+        assert_eq!(gather(OMsg::Quit.into(), &mut pending).len(), 1);
+        assert_eq!(pending.len(), 3);
+    }
+
+    #[test]
+    fn requests_after_an_invalidation_are_not_gathered() {
+        let mut pending = VecDeque::new();
+        pending.push_back(OMainToolbar::GetSites.to_request());
+        pending.push_back(OMsg::InvalidateCache(QueryKind::Sites).into());
+        pending.push_back(OPackagesTree::GetSites.to_request());
+        let batch = gather(OVpinDialog::GetSites.to_request(), &mut pending);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(pending.len(), 2);
+    }
+
+    #[test]
+    fn requests_from_a_change_of_site_on_are_not_gathered() {
+        let mut pending = VecDeque::new();
+        pending.push_back(OMainToolbar::GetRoles.to_request());
+        pending.push_back(OPackagesTree::SetCurrentSite("hyderabad".to_string()).to_request());
+        pending.push_back(OPackagesTree::GetSites.to_request());
+        let batch = gather(OVpinDialog::GetSites.to_request(), &mut pending);
+        assert_eq!(batch.len(), 1);
+        assert_eq!(pending.len(), 3);
+        // the change of site itself is answered along with the requests after it
+        pending.pop_front();
+        let request = pending.pop_front().unwrap();
+        let batch = gather(request, &mut pending);
+        assert_eq!(batch.len(), 2);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn queries_run_once_per_batch() {
+        let mut backend = MemoryBackend::new();
+        backend.set_sites(&["portland"]);
+        let mut results = Results::new();
+        let mut cache = Cache::new(CachePolicy::disabled());
+        let mut stats = Stats::new();
+        for _ in 0..3 {
+            let mut db = SharedQueries::new(&mut backend, &mut results, &mut cache, &mut stats);
+            assert_eq!(db.sites().unwrap(), vec!["portland"]);
+        }
+        backend.fail_next("relation \"role\" does not exist");
+        for _ in 0..2 {
+            let mut db = SharedQueries::new(&mut backend, &mut results, &mut cache, &mut stats);
+            // the original error remains reachable, eg for its SQLSTATE code
+            let err = db.roles().unwrap_err();
+            assert!(err.source().is_some());
+        }
+        assert_eq!(backend.queries(), vec!["sites", "roles"]);
+        // only the queries run against the backend are recorded
+        assert_eq!(stats.get(QueryKind::Sites).unwrap().count, 1);
+        assert_eq!(stats.get(QueryKind::Roles).unwrap().errors, 1);
+    }
+
+    #[test]
+    fn cached_results_are_shared_across_batches() {
+        let mut backend = MemoryBackend::new();
+        backend.set_sites(&["portland"]);
+        let mut cache = Cache::new(CachePolicy::default());
+        let mut stats = Stats::new();
+        for _ in 0..2 {
+            let mut results = Results::new();
+            let mut db = SharedQueries::new(&mut backend, &mut results, &mut cache, &mut stats);
+            assert_eq!(db.sites().unwrap(), vec!["portland"]);
+            assert_eq!(db.packages().unwrap(), Vec::<String>::new());
+        }
+        assert_eq!(backend.queries(), vec!["sites", "packages", "packages"]);
+    }
+}
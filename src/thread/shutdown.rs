@@ -0,0 +1,83 @@
+//! Stopping the secondary thread. OMsg::Quit takes effect as soon as the intake
+//! receives it, rather than once the requests queued ahead of it are handled.
+use super::*;
+use std::fmt;
+use std::time::Duration;
+
+/// What the secondary thread does with outstanding requests when told to quit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownPolicy {
+    /// Answer the requests sent before OMsg::Quit, then quit. Requests queued
+    /// while disconnected from the database are answered with an
+    /// ErrorCategory::Connection error, rather than waiting upon the database.
+    Drain,
+    /// Cancel the running query and drop the queued requests, then quit at once
+    Abandon,
+}
+
+impl Default for ShutdownPolicy {
+    fn default() -> Self {
+        ShutdownPolicy::Abandon
+    }
+}
+
+/// Why the secondary thread failed to stop cleanly. It is up to the application
+/// what to do about it, eg to exit the process regardless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownError {
+    /// The thread did not stop within the timeout, and is left running in the
+    /// background
+    TimedOut(Duration),
+    /// The thread panicked while stopping
+    Panicked,
+}
+
+impl fmt::Display for ShutdownError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShutdownError::TimedOut(timeout) => {
+                write!(f, "Secondary thread did not stop within {:?}", timeout)
+            }
+            ShutdownError::Panicked => write!(f, "Secondary thread panicked while stopping"),
+        }
+    }
+}
+
+impl std::error::Error for ShutdownError {}
+
+/// Tell the secondary thread spawned by `spawn_worker` to quit, and wait up to
+/// `timeout` for it to do so.
+///
+/// # Arguments
+/// * `sender` - Sends Requests to the secondary thread
+/// * `handle` - The JoinHandle returned by `spawn_worker`
+/// * `timeout` - How long to wait for the thread to stop
+///
+/// # Returns
+/// * Ok(()) if the thread stopped within the timeout
+/// * Err(ShutdownError) if it did not, or panicked
+pub fn shutdown_worker(
+    sender: &Sender<Request>,
+    handle: JoinHandle<()>,
+    timeout: Duration,
+) -> Result<(), ShutdownError> {
+    // the thread may have stopped already, if the application hung up
+    let _ = sender.send(OMsg::Quit.into());
+    join_worker(handle, timeout)
+}
+
+/// Wait up to `timeout` for the secondary thread to stop, having been told to
+/// quit. Should it not stop in time, it is left to finish in the background.
+pub(crate) fn join_worker(handle: JoinHandle<()>, timeout: Duration) -> Result<(), ShutdownError> {
+    // JoinHandle cannot be joined with a timeout, so a helper joins it, and
+    // reports back once it has
+    let (joined_sender, joined) = crossbeam_channel::bounded(1);
+    std::thread::spawn(move || {
+        let _ = joined_sender.send(handle.join().is_ok());
+    });
+    match joined.recv_timeout(timeout) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ShutdownError::Panicked),
+        Err(_) => Err(ShutdownError::TimedOut(timeout)),
+    }
+}
@@ -0,0 +1,235 @@
+//! Works out the site the application is running at, which the packages tree
+//! selects among the sites it lists. The site is taken from the configuration,
+//! else from an environment variable, else from a file mapping hostnames to
+//! sites, falling back to `DEFAULT_SITE` unless another default is configured with
+//! `SitePolicy::with_default`. The application may override it with
+//! `OPackagesTree::SetCurrentSite`.
+//!
+//! Each non comment line of the hosts file has the form `hostname site`. The
+//! hostname may be `*`, which matches any host, or begin with `*`, which matches
+//! any host ending with the rest, eg `*.portland.example.com portland`. The
+//! first matching line wins.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The environment variable naming the current site, unless configured otherwise
+pub const SITE_ENV_VAR: &str = "PACKRAT_SITE";
+
+/// The site used when no other source names one, unless configured otherwise.
+/// It is the site the packages tree selected before the current site was
+/// worked out.
+pub const DEFAULT_SITE: &str = "portland";
+
+/// Governs how the current site is worked out
+#[derive(Debug, Clone, PartialEq)]
+pub struct SitePolicy {
+    site: Option<String>,
+    env_var: Option<String>,
+    hosts_file: Option<PathBuf>,
+    default: String,
+}
+
+impl Default for SitePolicy {
+    fn default() -> Self {
+        Self {
+            site: None,
+            env_var: Some(SITE_ENV_VAR.to_string()),
+            hosts_file: None,
+            default: DEFAULT_SITE.to_string(),
+        }
+    }
+}
+
+impl SitePolicy {
+    /// New up a SitePolicy which reads `SITE_ENV_VAR`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run at `site`, regardless of the environment and the hosts file
+    pub fn with_site<S: Into<String>>(mut self, site: S) -> Self {
+        self.site = Some(site.into());
+        self
+    }
+
+    /// Read the current site from the environment variable `var`
+    pub fn with_env_var<S: Into<String>>(mut self, var: S) -> Self {
+        self.env_var = Some(var.into());
+        self
+    }
+
+    /// Ignore the environment
+    pub fn without_env(mut self) -> Self {
+        self.env_var = None;
+        self
+    }
+
+    /// Look up the host in the hosts file at `path`
+    pub fn with_hosts_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.hosts_file = Some(path.into());
+        self
+    }
+
+    /// Fall back to `site` should no other source name one, rather than
+    /// `DEFAULT_SITE`
+    pub fn with_default<S: Into<String>>(mut self, site: S) -> Self {
+        self.default = site.into();
+        self
+    }
+
+    /// Work out the current site, from the process' environment and hostname
+    pub fn resolve(&self) -> String {
+        self.resolve_with(|var| std::env::var(var).ok(), hostname)
+    }
+
+    /// Work out the current site
+    ///
+    /// # Arguments
+    /// * `env` - Looks up an environment variable
+    /// * `host` - Looks up the hostname, should the hosts file be consulted
+    ///
+    /// # Returns
+    /// * The first site named by the configuration, the environment or the hosts
+    ///   file, else the default site
+    pub(crate) fn resolve_with<E, H>(&self, env: E, host: H) -> String
+    where
+        E: Fn(&str) -> Option<String>,
+        H: FnOnce() -> Option<String>,
+    {
+        if let Some(site) = &self.site {
+            return site.clone();
+        }
+        if let Some(site) = self
+            .env_var
+            .as_ref()
+            .and_then(|var| env(var.as_str()))
+            .filter(|site| !site.trim().is_empty())
+        {
+            return site.trim().to_string();
+        }
+        if let Some(path) = &self.hosts_file {
+            match host() {
+                Some(host) => match find_site(path, &host) {
+                    Some(site) => return site,
+                    None => log::warn!("No site is mapped to {} in {}", host, path.display()),
+                },
+                None => log::warn!("Unable to look up the hostname to find the current site"),
+            }
+        }
+        self.default.clone()
+    }
+}
+
+/// Read the hosts file at `path` and return the site of the first entry matching
+/// `host`, if any
+pub(crate) fn find_site(path: &Path, host: &str) -> Option<String> {
+    match fs::read_to_string(path) {
+        Ok(contents) => lookup(&contents, host),
+        Err(err) => {
+            log::warn!("Unable to read hosts file {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Return the site of the first entry in `contents` matching `host`
+pub(crate) fn lookup(contents: &str, host: &str) -> Option<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next()) {
+                (Some(pattern), Some(site)) => Some((pattern, site)),
+                _ => None,
+            }
+        })
+        .find(|(pattern, _)| matches(pattern, host))
+        .map(|(_, site)| site.to_string())
+}
+
+// whether `pattern` matches `host`, ignoring case as dns does
+fn matches(pattern: &str, host: &str) -> bool {
+    let host = host.to_lowercase();
+    let pattern = pattern.to_lowercase();
+    match pattern.strip_prefix('*') {
+        Some(suffix) => host.ends_with(suffix),
+        None => host == pattern,
+    }
+}
+
+// the hostname of the machine, as the system reports it, else from
+// /etc/hostname. $HOSTNAME is not consulted, as shells set it without
+// exporting it, so it is rarely in the environment of a gui application.
+fn hostname() -> Option<String> {
+    ::hostname::get()
+        .ok()
+        .and_then(|host| host.into_string().ok())
+        .filter(|host| !host.trim().is_empty())
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTS: &str = "
+# facility workstations
+*.pd.example.com    portland
+*.mtl.example.com   montreal
+render01            hyderabad
+";
+
+    #[test]
+    fn hosts_are_matched_by_suffix_or_name() {
+        assert_eq!(
+            lookup(HOSTS, "ws12.PD.example.com"),
+            Some("portland".into())
+        );
+        assert_eq!(lookup(HOSTS, "render01"), Some("hyderabad".into()));
+        assert_eq!(lookup(HOSTS, "render02"), None);
+        assert_eq!(lookup("* london", "anything"), Some("london".into()));
+    }
+
+    #[test]
+    fn the_hostname_is_looked_up() {
+        let host = hostname().expect("no hostname");
+        assert_eq!(host, host.trim());
+    }
+
+    #[test]
+    fn configuration_wins_over_environment_and_environment_over_hosts() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("site-hosts");
+        fs::write(&path, HOSTS).unwrap();
+        let env = |var: &str| match var {
+            SITE_ENV_VAR => Some("montreal".to_string()),
+            _ => None,
+        };
+        let host = || Some("ws12.pd.example.com".to_string());
+        let policy = SitePolicy::new().with_hosts_file(&path);
+        assert_eq!(policy.resolve_with(env, host), "montreal");
+        assert_eq!(
+            policy.clone().with_site("london").resolve_with(env, host),
+            "london"
+        );
+        assert_eq!(
+            policy.clone().without_env().resolve_with(env, host),
+            "portland"
+        );
+        assert_eq!(
+            SitePolicy::new().without_env().resolve_with(env, host),
+            "portland"
+        );
+        assert_eq!(
+            SitePolicy::new()
+                .without_env()
+                .with_default("london")
+                .resolve_with(env, host),
+            "london"
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! Statement timeouts. A watchdog cancels any query which runs past the timeout
+//! for its kind, and the request is answered with an `ErrorCategory::Timeout`
+//! error. The timeouts are enforced from the client, via the backend's
+//! CancelQuery handle, so they apply to any backend.
+use super::*;
+use crate::{backend::CancelQuery, outgoing::QueryKind};
+use crossbeam_channel::RecvTimeoutError;
+use std::collections::HashMap;
+
+/// Governs how long each kind of query may run before it is cancelled. Kinds of
+/// query without a timeout of their own fall back to the default, if any.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TimeoutPolicy {
+    default: Option<Duration>,
+    overrides: HashMap<QueryKind, Duration>,
+}
+
+impl TimeoutPolicy {
+    /// New up a TimeoutPolicy which lets queries run for as long as they take
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Cancel any query which runs for longer than `timeout`, unless its kind
+    /// has a timeout of its own
+    pub fn with_default(mut self, timeout: Duration) -> Self {
+        self.default = Some(timeout);
+        self
+    }
+
+    /// Cancel `kind` of query should it run for longer than `timeout`
+    pub fn with_timeout(mut self, kind: QueryKind, timeout: Duration) -> Self {
+        self.overrides.insert(kind, timeout);
+        self
+    }
+
+    /// The timeout of `kind` of query, if any
+    pub fn timeout(&self, kind: QueryKind) -> Option<Duration> {
+        self.overrides.get(&kind).cloned().or(self.default)
+    }
+}
+
+/// Spawn the watchdog thread. The secondary thread sends it the deadline of each
+/// request it starts. Should the request still be running at its deadline, the
+/// watchdog times it out. The watchdog stops once the secondary thread drops
+/// its Sender.
+///
+/// # Arguments
+/// * `in_flight` - Tracks the request being handled by the secondary thread
+/// * `deadlines` - Receives the id and deadline of each request as it starts
+pub(crate) fn spawn_watchdog<K>(
+    in_flight: Arc<InFlight<K>>,
+    deadlines: Receiver<(RequestId, Instant)>,
+) -> JoinHandle<()>
+where
+    K: CancelQuery + Send + Sync + 'static,
+{
+    std::thread::spawn(move || {
+        let mut next = deadlines.recv().ok();
+        while let Some((id, deadline)) = next {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            next = match deadlines.recv_timeout(wait) {
+                // a later request has started, so this one has finished
+                Ok(later) => Some(later),
+                Err(RecvTimeoutError::Timeout) => {
+                    in_flight.time_out(id);
+                    deadlines.recv().ok()
+                }
+                Err(RecvTimeoutError::Disconnected) => None,
+            };
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds_fall_back_to_the_default_timeout() {
+        let policy = TimeoutPolicy::none()
+            .with_default(Duration::from_secs(30))
+            .with_timeout(QueryKind::Levels, Duration::from_secs(90));
+        assert_eq!(
+            policy.timeout(QueryKind::Levels),
+            Some(Duration::from_secs(90))
+        );
+        assert_eq!(
+            policy.timeout(QueryKind::Roles),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(TimeoutPolicy::none().timeout(QueryKind::Roles), None);
+    }
+}
@@ -1,124 +1,168 @@
 use super::*;
 
 /// perform a submatch against the OVpinDialog msg
-pub(crate) fn match_vpin_dialog(
+pub(crate) fn match_vpin_dialog<B: PackratBackend, N: Notifier>(
     msg: OVpinDialog,
-    db: &mut packybara::db::packrat::PackratDb,
-    conductor: &mut qt_thread_conductor::conductor::Conductor<Event>,
-    sender: &Sender<IMsg>,
-) {
+    request: RequestId,
+    db: &mut B,
+    conductor: &mut N,
+    mailbox: &Mailbox,
+) -> Outcome {
     match msg {
         OVpinDialog::GetRoles => {
-            let roles = match db.find_all_roles().query() {
+            let roles = match db.roles() {
                 Ok(roles) => roles,
                 Err(err) => {
-                    sender
-                        .send(IMsg::Error(format!("Unable to get roles from db: {}", err)))
-                        .expect("unable to send error msg");
-                    conductor.signal(Event::Error);
-                    return;
+                    return query_failed(
+                        "Unable to get roles from db",
+                        err,
+                        OVpinDialog::FAMILY,
+                        request,
+                        conductor,
+                        mailbox,
+                    );
                 }
             };
-            let roles = roles
-                .into_iter()
-                .map(|mut x| std::mem::replace(&mut x.role, String::new()))
-                .collect::<Vec<_>>();
-            sender
-                .send(IVpinDialog::Roles(roles).to_imsg())
-                .expect("unable to send roles");
-            conductor.signal(VpinDialog::UpdateRoles.to_event());
+            deliver(
+                IVpinDialog::Roles(roles).to_imsg(),
+                VpinDialog::UpdateRoles.to_event(),
+                Some(request),
+                conductor,
+                mailbox,
+            );
         }
 
         OVpinDialog::GetSites => {
-            let sites = match db.find_all_sites().query() {
+            let sites = match db.sites() {
                 Ok(sites) => sites,
                 Err(e) => {
-                    sender
-                        .send(IMsg::Error(format!("Unable to get sites from db: {}", e)))
-                        .expect("unable to send error msg");
-                    conductor.signal(Event::Error);
-                    return;
+                    return query_failed(
+                        "Unable to get sites from db",
+                        e,
+                        OVpinDialog::FAMILY,
+                        request,
+                        conductor,
+                        mailbox,
+                    );
                 }
             };
-            // we use std::mem::replace because this should be a bit more efficient
-            // than clone, and certainly more
-            let sites = sites
-                .into_iter()
-                .map(|mut x| std::mem::replace(&mut x.name, String::new()))
-                .collect::<Vec<_>>();
-            sender
-                .send(IVpinDialog::Sites(sites).to_imsg())
-                .expect("unable to send sites");
-            conductor.signal(VpinDialog::UpdateSites.to_event());
+            deliver(
+                IVpinDialog::Sites(sites).to_imsg(),
+                VpinDialog::UpdateSites.to_event(),
+                Some(request),
+                conductor,
+                mailbox,
+            );
         }
 
         OVpinDialog::GetLevels(ref show) => {
-            let levels = match db.find_all_levels().show(show).query() {
+            let levels = match db.levels(show) {
                 Ok(levels) => levels,
                 Err(e) => {
-                    sender
-                        .send(IMsg::Error(format!(
-                            "Unable to get levels from db for {}: {}",
-                            show, e
-                        )))
-                        .expect("unable to send error msg");
-                    conductor.signal(Event::Error);
-                    return;
+                    return query_failed(
+                        &format!("Unable to get levels from db for {}", show),
+                        e,
+                        OVpinDialog::FAMILY,
+                        request,
+                        conductor,
+                        mailbox,
+                    );
                 }
             };
-            let mut level_map = LevelMap::new();
-            // If we dont have any sequences or shots, then only the show will be returned.
-            // The length of the returned vec will be 1. We can return an empty map and continue.
-            if levels.len() == 1 {
-                sender
-                    .send(IVpinDialog::Levels(level_map).to_imsg())
-                    .expect("Unable to send levelmap");
-                conductor.signal(VpinDialog::UpdateLevels.to_event());
-                return;
-            }
-            // Now we get rid of the show name
-            let levels = &levels[1..];
-            // initialize a blank key (sequence)
-            let mut key = "".to_string();
-            // and an empty vec for shots
-            let mut shots: Vec<String> = Vec::new();
-            for level in levels {
-                let pieces = level.level.split(".").collect::<Vec<_>>();
-                let pieces_len = pieces.len();
-                // if we have two pieces, they are show and sequence.
-                if pieces_len == 2 {
-                    // if the key is blank, then we have only just begun
-                    if &key == "" {
-                        key = pieces[1].to_string();
-                    } else {
-                        // we must have a previous sequence. It is time to insert
-                        // whatever sequence and shots we have collected thus far, and
-                        // set them up for the new sequence
-                        let old_shots = std::mem::replace(&mut shots, Vec::new());
-                        level_map.insert(key.clone(), old_shots);
-                        // and the new sequence is in the second spot in the vector
-                        key = pieces[1].to_string();
-                    }
-                // we are in a shot
-                } else if pieces_len == 3 {
-                    shots.push(pieces[2].to_string());
-                } else {
-                    // if we are not in a show sequence or shot then what is going on?
-                    panic!("Incorrect number of pieces from get_all_levels");
+            let level_map = match to_level_map(&levels) {
+                Ok(level_map) => level_map,
+                Err(err) => {
+                    let error = IError::new(
+                        OVpinDialog::FAMILY,
+                        ErrorCategory::Parse,
+                        format!("Unable to parse levels for {}: {}", show, err),
+                    );
+                    report(error.with_request(request), conductor, mailbox);
+                    return Outcome::Handled;
                 }
-            }
-            // we need to account for the last sequence and potential shots
-            // as they will never get inserted in the previous loop
-            // Of course, there is always the possiblity that we have no sequences
-            // or shots. So we guard against that.
-            if &key != "" {
-                level_map.insert(key, shots);
-            }
+            };
             // now lets send our work
-            sender
-                .send(IVpinDialog::Levels(level_map).to_imsg())
-                .expect("Unable to send levelmap");
-            conductor.signal(VpinDialog::UpdateLevels.to_event());
+            deliver(
+                IVpinDialog::Levels(level_map).to_imsg(),
+                VpinDialog::UpdateLevels.to_event(),
+                Some(request),
+                conductor,
+                mailbox,
+            );
+        }
+    }
+    Outcome::Handled
+}
+
+// Convert the levels of a show, as returned by the backend, into a map of
+// sequences to their shots
+fn to_level_map(levels: &[String]) -> Result<LevelMap, String> {
+    let mut level_map = LevelMap::new();
+    // If we dont have any sequences or shots, then only the show will be returned.
+    // The length of the returned vec will be 1. We can return an empty map and continue.
+    if levels.len() <= 1 {
+        return Ok(level_map);
+    }
+    // Now we get rid of the show name
+    let levels = &levels[1..];
+    // initialize a blank key (sequence)
+    let mut key = "".to_string();
+    // and an empty vec for shots
+    let mut shots: Vec<String> = Vec::new();
+    for level in levels {
+        let pieces = level.split(".").collect::<Vec<_>>();
+        let pieces_len = pieces.len();
+        // if we have two pieces, they are show and sequence.
+        if pieces_len == 2 {
+            // if the key is blank, then we have only just begun
+            if &key == "" {
+                key = pieces[1].to_string();
+            } else {
+                // we must have a previous sequence. It is time to insert
+                // whatever sequence and shots we have collected thus far, and
+                // set them up for the new sequence
+                let old_shots = std::mem::replace(&mut shots, Vec::new());
+                level_map.insert(key.clone(), old_shots);
+                // and the new sequence is in the second spot in the vector
+                key = pieces[1].to_string();
+            }
+        // we are in a shot
+        } else if pieces_len == 3 {
+            shots.push(pieces[2].to_string());
+        } else {
+            // if we are not in a show sequence or shot then what is going on?
+            return Err(format!("Incorrect number of pieces in level '{}'", level));
         }
     }
+    // we need to account for the last sequence and potential shots
+    // as they will never get inserted in the previous loop
+    // Of course, there is always the possiblity that we have no sequences
+    // or shots. So we guard against that.
+    if &key != "" {
+        level_map.insert(key, shots);
+    }
+    Ok(level_map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn can_convert_levels_to_level_map() {
+        let levels = strings(&["dev01", "dev01.rd", "dev01.rd.9999", "dev01.ab"]);
+        let level_map = to_level_map(&levels).unwrap();
+        assert_eq!(level_map.get("rd"), Some(&strings(&["9999"])));
+        assert_eq!(level_map.get("ab"), Some(&Vec::new()));
+    }
+
+    #[test]
+    fn malformed_levels_are_reported() {
+        let levels = strings(&["dev01", "dev01.rd.9999.extra"]);
+        assert!(to_level_map(&levels).is_err());
+    }
 }